@@ -0,0 +1,264 @@
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use log::{error, info};
+
+use crate::brightness::{self, Transitions};
+use crate::commands;
+use crate::configuration::{AutoBrightness, Configuration, LuxPoint};
+use crate::error::GlueError;
+use crate::monitor::Monitor;
+
+/// Time of the last manual brightness change and whether the idle `dim` stage is
+/// active, shared between the IPC server, the idle stages and the sensor
+#[derive(Clone, Debug, Default)]
+pub(crate) struct ManualBrightness {
+    changed: Arc<Mutex<Option<Instant>>>,
+    dimmed: Arc<AtomicBool>,
+}
+
+impl ManualBrightness {
+    pub(crate) fn touch(&self) {
+        *self.changed.lock().unwrap() = Some(Instant::now());
+    }
+
+    /// Pauses the sensor until the dimmed brightness is restored
+    pub(crate) fn dim(&self, dimmed: bool) {
+        self.dimmed.store(dimmed, Ordering::SeqCst);
+    }
+
+    fn paused(&self, pause: Duration) -> bool {
+        self.dimmed.load(Ordering::SeqCst)
+            || self
+                .changed
+                .lock()
+                .unwrap()
+                .is_some_and(|changed| changed.elapsed() < pause)
+    }
+}
+
+/// Automatic brightness, following an IIO illuminance sensor
+pub(crate) struct AmbientLight {
+    config: Arc<Configuration>,
+    sensor: PathBuf,
+    manual: ManualBrightness,
+    transitions: Transitions,
+    /// Smoothed illuminance (in lux)
+    lux: Option<f64>,
+    /// Brightness last set by the sensor (in %)
+    brightness: Option<u32>,
+}
+
+#[async_trait]
+impl Monitor for AmbientLight {
+    async fn update(&mut self) -> Result<(), GlueError> {
+        if self.manual.paused(self.config.brightness.auto.pause) {
+            // Start over once the pause ends, even if the light did not change
+            self.brightness = None;
+            return Ok(());
+        }
+        let Some(lux) = read_lux(&self.sensor).await else {
+            return Ok(());
+        };
+        if let Some(target) = self.target(lux) {
+            info!("ambient light: {lux:.1} lux -> brightness: {target}");
+            let request = commands::Brightness::Set {
+                percent: target,
+                device: None,
+            };
+            // Animated like the requests, so it cancels and is canceled by them
+            brightness::transition(request, self.config.clone(), &self.transitions).await?;
+        }
+        Ok(())
+    }
+
+    async fn event(&self) {}
+}
+
+impl AmbientLight {
+    /// Returns `None` if automatic brightness is disabled or no sensor was found
+    pub(crate) fn try_new(
+        config: Arc<Configuration>,
+        manual: ManualBrightness,
+        transitions: Transitions,
+    ) -> Option<Self> {
+        let auto = &config.brightness.auto;
+        if !auto.enabled {
+            return None;
+        }
+        let sensor = match &auto.sensor {
+            Some(sensor) => Some(PathBuf::from(sensor)),
+            None => find_sensor(Path::new(&auto.path)),
+        };
+        let Some(sensor) = sensor else {
+            error!("No illuminance sensor found in {}", auto.path);
+            return None;
+        };
+        info!("Automatic brightness using {}", sensor.to_string_lossy());
+        Some(Self {
+            config,
+            sensor,
+            manual,
+            transitions,
+            lux: None,
+            brightness: None,
+        })
+    }
+
+    /// Smooths the measurement and returns the new brightness, if it differs
+    /// from the current one by at least the hysteresis.
+    fn target(&mut self, lux: f64) -> Option<u32> {
+        let auto = &self.config.brightness.auto;
+        let lux = match self.lux {
+            Some(previous) => previous + auto.smoothing.clamp(0.0, 1.0) * (lux - previous),
+            None => lux,
+        };
+        self.lux = Some(lux);
+        let target = interpolate(&auto.points, lux);
+        match self.brightness {
+            Some(current) if current.abs_diff(target) < auto.hysteresis => None,
+            _ => {
+                self.brightness = Some(target);
+                Some(target)
+            }
+        }
+    }
+}
+
+fn find_sensor(path: &Path) -> Option<PathBuf> {
+    let mut sensors = std::fs::read_dir(path)
+        .ok()?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.join("in_illuminance_raw").exists() || path.join("in_illuminance_input").exists()
+        })
+        .collect::<Vec<_>>();
+    sensors.sort();
+    sensors.into_iter().next()
+}
+
+/// Illuminance in lux, `(raw + offset) * scale` or the processed `input` value
+async fn read_lux(sensor: &Path) -> Option<f64> {
+    let read = |filename: &'static str| async move {
+        tokio::fs::read_to_string(sensor.join(filename))
+            .await
+            .ok()?
+            .trim_end()
+            .parse::<f64>()
+            .ok()
+    };
+    if let Some(lux) = read("in_illuminance_input").await {
+        return Some(lux);
+    }
+    let raw = read("in_illuminance_raw").await?;
+    let offset = read("in_illuminance_offset").await.unwrap_or(0.0);
+    let scale = read("in_illuminance_scale").await.unwrap_or(1.0);
+    Some((raw + offset) * scale)
+}
+
+/// Linear interpolation between the configured points, clamped to the first and last point
+fn interpolate(points: &[LuxPoint], lux: f64) -> u32 {
+    let (Some(first), Some(last)) = (points.first(), points.last()) else {
+        return AutoBrightness::default().points[0].brightness;
+    };
+    if lux <= first.lux {
+        return first.brightness;
+    }
+    for window in points.windows(2) {
+        let (low, high) = (&window[0], &window[1]);
+        if lux <= high.lux {
+            let ratio = (lux - low.lux) / (high.lux - low.lux);
+            let brightness =
+                low.brightness as f64 + ratio * (high.brightness as f64 - low.brightness as f64);
+            return brightness.round() as u32;
+        }
+    }
+    last.brightness
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn setup_test_environment() -> (TempDir, Configuration) {
+        let temp_dir = TempDir::new().unwrap();
+        let sensor = temp_dir.path().join("iio:device0");
+        std::fs::create_dir_all(&sensor).unwrap();
+        std::fs::create_dir_all(temp_dir.path().join("iio:device1")).unwrap();
+        std::fs::write(sensor.join("in_illuminance_raw"), "200\n").unwrap();
+        std::fs::write(sensor.join("in_illuminance_scale"), "0.5\n").unwrap();
+
+        let mut config = Configuration::default();
+        config.brightness.auto = AutoBrightness {
+            enabled: true,
+            path: temp_dir.path().to_string_lossy().to_string(),
+            points: vec![
+                LuxPoint {
+                    lux: 0.0,
+                    brightness: 10,
+                },
+                LuxPoint {
+                    lux: 200.0,
+                    brightness: 50,
+                },
+            ],
+            smoothing: 0.5,
+            hysteresis: 5,
+            ..Default::default()
+        };
+        (temp_dir, config)
+    }
+
+    #[tokio::test]
+    async fn test_read_sensor() {
+        let (temp_dir, config) = setup_test_environment();
+        let ambient = AmbientLight::try_new(
+            config.into(),
+            ManualBrightness::default(),
+            Transitions::default(),
+        )
+        .unwrap();
+        assert_eq!(ambient.sensor, temp_dir.path().join("iio:device0"));
+        assert_eq!(read_lux(&ambient.sensor).await, Some(100.0));
+    }
+
+    #[test]
+    fn test_target_smoothing_and_hysteresis() {
+        let (_temp_dir, config) = setup_test_environment();
+        let mut ambient = AmbientLight::try_new(
+            config.into(),
+            ManualBrightness::default(),
+            Transitions::default(),
+        )
+        .unwrap();
+        assert_eq!(ambient.target(100.0), Some(30));
+        // smoothed to 105 lux, below the hysteresis
+        assert_eq!(ambient.target(110.0), None);
+        // smoothed to 152.5 lux
+        assert_eq!(ambient.target(200.0), Some(41));
+        assert_eq!(ambient.target(1000.0), Some(50));
+    }
+
+    #[test]
+    fn test_manual_pause() {
+        let manual = ManualBrightness::default();
+        assert!(!manual.paused(Duration::from_secs(60)));
+        manual.touch();
+        assert!(manual.paused(Duration::from_secs(60)));
+        assert!(!manual.paused(Duration::ZERO));
+    }
+
+    #[test]
+    fn test_dim_pause() {
+        let manual = ManualBrightness::default();
+        manual.dim(true);
+        assert!(manual.paused(Duration::ZERO));
+        manual.dim(false);
+        assert!(!manual.paused(Duration::ZERO));
+    }
+}
@@ -13,6 +13,7 @@ struct Battery {
     state: BatteryStatus,
     capacity: u8,
     icon: char,
+    ac: Option<bool>,
 }
 
 impl Battery {
@@ -20,10 +21,12 @@ impl Battery {
         let state = Self::read_state(&config.battery.path)?;
         let capacity = Self::read_capacity(&config.battery.path)?;
         let icon = Self::icon(&state, capacity, config);
+        let ac = AcAdapter::find(&config.battery.path)?.map(|adapter| adapter.online);
         Ok(Self {
             state,
             capacity,
             icon,
+            ac,
         })
     }
 
@@ -55,18 +58,190 @@ impl Battery {
     }
 
     fn read_sys_file(filename: &str, battery_path: &str) -> Result<String, BatteryError> {
-        let filepath = Path::new(battery_path).join(filename);
-        let mut file = fs::OpenOptions::new()
-            .read(true)
-            .open(&filepath)
-            .map_err(|x| {
-                BatteryError::ReadFile(filepath.to_string_lossy().to_string(), x.to_string())
-            })?;
-        let mut content = String::new();
-        file.read_to_string(&mut content).map_err(|x| {
+        read_sys_file(&Path::new(battery_path).join(filename))
+    }
+}
+
+fn read_sys_file(filepath: &Path) -> Result<String, BatteryError> {
+    let mut file = fs::OpenOptions::new()
+        .read(true)
+        .open(filepath)
+        .map_err(|x| {
             BatteryError::ReadFile(filepath.to_string_lossy().to_string(), x.to_string())
         })?;
-        Ok(content)
+    let mut content = String::new();
+    file.read_to_string(&mut content).map_err(|x| {
+        BatteryError::ReadFile(filepath.to_string_lossy().to_string(), x.to_string())
+    })?;
+    Ok(content)
+}
+
+/// Current power draw in W, from `power_now` or `current_now * voltage_now`
+pub(crate) fn power_draw(battery_path: &str) -> Option<f32> {
+    let path = Path::new(battery_path);
+    let read = |filename: &str| -> Option<f32> {
+        read_sys_file(&path.join(filename))
+            .ok()?
+            .trim_end()
+            .parse::<f32>()
+            .ok()
+    };
+    match read("power_now") {
+        Some(power) => Some(power / 1e6),
+        None => Some(read("current_now")? * read("voltage_now")? / 1e12),
+    }
+}
+
+fn write_sys_file(filepath: &Path, value: &str) -> Result<(), BatteryError> {
+    fs::write(filepath, value)
+        .map_err(|x| BatteryError::WriteFile(filepath.to_string_lossy().to_string(), x.to_string()))
+}
+
+/// Mains power supply, living next to the battery in `/sys/class/power_supply`
+#[derive(Serialize)]
+pub(crate) struct AcAdapter {
+    name: String,
+    pub(crate) online: bool,
+}
+
+impl AcAdapter {
+    /// Searches the siblings of the battery for an adapter named `AC*` or of `type=Mains`.
+    /// Returns `None` on systems without one (e.g. desktops).
+    pub(crate) fn find(battery_path: &str) -> Result<Option<Self>, BatteryError> {
+        let Some(supplies) = Path::new(battery_path).parent() else {
+            return Ok(None);
+        };
+        let Ok(entries) = fs::read_dir(supplies) else {
+            return Ok(None);
+        };
+        let mut candidates = entries
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| Self::is_mains(path))
+            .collect::<Vec<_>>();
+        candidates.sort();
+        let Some(path) = candidates.first() else {
+            return Ok(None);
+        };
+        let online = read_sys_file(&path.join("online"))?;
+        Ok(Some(Self {
+            name: path
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            online: online.trim_end() == "1",
+        }))
+    }
+
+    fn is_mains(path: &Path) -> bool {
+        let named_ac = path
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with("AC"));
+        let typed_mains = read_sys_file(&path.join("type"))
+            .map(|kind| kind.trim_end() == "Mains")
+            .unwrap_or(false);
+        (named_ac || typed_mains) && path.join("online").exists()
+    }
+}
+
+/// Charge thresholds of the battery, `start` is not supported by every vendor
+#[derive(Serialize, Debug, PartialEq)]
+pub(crate) struct ChargeLimit {
+    start: Option<u8>,
+    end: u8,
+}
+
+/// Wear of the battery, comparing the current full capacity to the design capacity
+#[derive(Serialize)]
+struct BatteryHealth {
+    full: f32,
+    design: f32,
+    /// `Wh` for batteries reporting `energy_*`, `Ah` for `charge_*`
+    unit: &'static str,
+    /// `full` relative to `design` (in %)
+    health: f32,
+    cycle_count: Option<u32>,
+}
+
+impl BatteryHealth {
+    fn read(battery_path: &str) -> Result<Self, BatteryError> {
+        let path = Path::new(battery_path);
+        let (prefix, unit) = match path.join("energy_full").exists() {
+            true => ("energy", "Wh"),
+            false => ("charge", "Ah"),
+        };
+        let read = |filename: String| -> Result<f32, BatteryError> {
+            read_sys_file(&path.join(filename))?
+                .trim_end()
+                .parse::<f32>()
+                .map(|x| x / 1e6)
+                .map_err(|x| BatteryError::ParseHealth(x.to_string()))
+        };
+        let full = read(format!("{prefix}_full"))?;
+        let design = read(format!("{prefix}_full_design"))?;
+        let cycle_count = read_sys_file(&path.join("cycle_count"))
+            .ok()
+            .and_then(|x| x.trim_end().parse::<u32>().ok());
+        Ok(Self {
+            full,
+            design,
+            unit,
+            health: if design > 0.0 {
+                full / design * 100.0
+            } else {
+                0.0
+            },
+            cycle_count,
+        })
+    }
+}
+
+const CHARGE_START: &str = "charge_control_start_threshold";
+const CHARGE_END: &str = "charge_control_end_threshold";
+
+impl ChargeLimit {
+    fn read(battery_path: &str) -> Result<Self, BatteryError> {
+        let path = Path::new(battery_path);
+        let start = match path.join(CHARGE_START).exists() {
+            true => Some(Self::read_threshold(&path.join(CHARGE_START))?),
+            false => None,
+        };
+        let end = Self::read_threshold(&path.join(CHARGE_END))?;
+        Ok(Self { start, end })
+    }
+
+    fn read_threshold(path: &Path) -> Result<u8, BatteryError> {
+        read_sys_file(path)?
+            .trim_end()
+            .parse::<u8>()
+            .map_err(|x| BatteryError::ParseThreshold(x.to_string()))
+    }
+
+    /// Writes the thresholds, only the end threshold if `start` is `None`
+    fn write(battery_path: &str, start: Option<u8>, end: u8) -> Result<(), BatteryError> {
+        if start.is_some_and(|start| start >= end) || end > 100 {
+            return Err(BatteryError::InvalidThreshold(
+                start.unwrap_or_default(),
+                end,
+            ));
+        }
+        let path = Path::new(battery_path);
+        let current = Self::read(battery_path)?;
+        let Some(start) = start else {
+            return write_sys_file(&path.join(CHARGE_END), &end.to_string());
+        };
+        if current.start.is_none() {
+            return Err(BatteryError::Unsupported(CHARGE_START));
+        }
+        // The kernel rejects a start threshold above the current end threshold (and vice versa),
+        // therefore the order of the writes depends on the direction of the change.
+        if start >= current.end {
+            write_sys_file(&path.join(CHARGE_END), &end.to_string())?;
+            write_sys_file(&path.join(CHARGE_START), &start.to_string())
+        } else {
+            write_sys_file(&path.join(CHARGE_START), &start.to_string())?;
+            write_sys_file(&path.join(CHARGE_END), &end.to_string())
+        }
     }
 }
 
@@ -117,6 +292,30 @@ pub fn get_battery(config: &Configuration) -> Result<String, BatteryError> {
     Ok(serde_json::to_string(&battery).unwrap())
 }
 
+pub fn get_health(config: &Configuration) -> Result<String, BatteryError> {
+    let health = BatteryHealth::read(&config.battery.path)?;
+    Ok(serde_json::to_string(&health).unwrap())
+}
+
+pub fn get_ac(config: &Configuration) -> Result<String, BatteryError> {
+    let adapter = AcAdapter::find(&config.battery.path)?
+        .ok_or(BatteryError::NoAcAdapter(config.battery.path.clone()))?;
+    Ok(serde_json::to_string(&adapter).unwrap())
+}
+
+pub fn get_charge_limit(config: &Configuration) -> Result<String, BatteryError> {
+    let limit = ChargeLimit::read(&config.battery.path)?;
+    Ok(serde_json::to_string(&limit).unwrap())
+}
+
+pub fn set_charge_limit(
+    config: &Configuration,
+    start: Option<u8>,
+    end: u8,
+) -> Result<(), BatteryError> {
+    ChargeLimit::write(&config.battery.path, start, end)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -144,6 +343,7 @@ mod tests {
                 full: '🔋',
                 charging_states: vec!['▁', '▂', '▃', '▄', '▅'],
                 path: bat_dir.to_string_lossy().to_string(),
+                ..Default::default()
             },
             ..Default::default()
         };
@@ -169,5 +369,81 @@ mod tests {
         assert_eq!(output_json["state"], "Charging");
         assert_eq!(output_json["capacity"], 75);
         assert_eq!(output_json["icon"], "⚡");
+        assert_eq!(output_json["ac"], serde_json::Value::Null);
+    }
+
+    #[test]
+    fn test_ac_adapter() {
+        let (temp_dir, config) = setup_test_environment();
+        let ac_dir = temp_dir.path().join("ADP1");
+        std::fs::create_dir_all(&ac_dir).unwrap();
+        writeln!(File::create(ac_dir.join("type")).unwrap(), "Mains").unwrap();
+        writeln!(File::create(ac_dir.join("online")).unwrap(), "1").unwrap();
+
+        let output: serde_json::Value = serde_json::from_str(&get_ac(&config).unwrap()).unwrap();
+        assert_eq!(output["name"], "ADP1");
+        assert_eq!(output["online"], true);
+
+        let output: serde_json::Value =
+            serde_json::from_str(&get_battery(&config).unwrap()).unwrap();
+        assert_eq!(output["ac"], true);
+    }
+
+    #[test]
+    fn test_battery_health() {
+        let (temp_dir, config) = setup_test_environment();
+        let bat_dir = temp_dir.path().join("BAT0");
+        writeln!(
+            File::create(bat_dir.join("energy_full")).unwrap(),
+            "45000000"
+        )
+        .unwrap();
+        writeln!(
+            File::create(bat_dir.join("energy_full_design")).unwrap(),
+            "50000000"
+        )
+        .unwrap();
+        writeln!(File::create(bat_dir.join("cycle_count")).unwrap(), "312").unwrap();
+
+        let output: serde_json::Value =
+            serde_json::from_str(&get_health(&config).unwrap()).unwrap();
+        assert_eq!(output["unit"], "Wh");
+        assert_eq!(output["health"], 90.0);
+        assert_eq!(output["cycle_count"], 312);
+    }
+
+    #[test]
+    fn test_charge_limit() {
+        let (temp_dir, config) = setup_test_environment();
+        let bat_dir = temp_dir.path().join("BAT0");
+        writeln!(File::create(bat_dir.join(CHARGE_START)).unwrap(), "0").unwrap();
+        writeln!(File::create(bat_dir.join(CHARGE_END)).unwrap(), "100").unwrap();
+
+        assert!(set_charge_limit(&config, Some(80), 60).is_err());
+        assert!(set_charge_limit(&config, Some(40), 101).is_err());
+        set_charge_limit(&config, Some(75), 80).unwrap();
+        assert_eq!(
+            ChargeLimit::read(&config.battery.path).unwrap(),
+            ChargeLimit {
+                start: Some(75),
+                end: 80
+            }
+        );
+    }
+    #[test]
+    fn test_charge_limit_without_start() {
+        let (temp_dir, config) = setup_test_environment();
+        let bat_dir = temp_dir.path().join("BAT0");
+        writeln!(File::create(bat_dir.join(CHARGE_END)).unwrap(), "100").unwrap();
+
+        assert!(set_charge_limit(&config, Some(75), 80).is_err());
+        set_charge_limit(&config, None, 80).unwrap();
+        assert_eq!(
+            ChargeLimit::read(&config.battery.path).unwrap(),
+            ChargeLimit {
+                start: None,
+                end: 80
+            }
+        );
     }
 }
@@ -1,113 +1,636 @@
+use std::sync::Arc;
+use std::time::Duration;
+
 use brightness::blocking::{brightness_devices, Brightness, BrightnessDevice};
-use serde::Serialize;
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+use tokio::time::interval;
 
 use crate::{
+    commands,
+    configuration::{Brightness as BrightnessConfiguration, Configuration, Curve},
+    daemon,
     error::{BrightnessError, GlueError},
     eww::{eww_update, EwwVariable},
-    key::{Changeable, FunctionKey},
+    utils::glob_match,
     Change,
 };
 
-#[derive(Serialize, Clone)]
+/// Time between two steps of an animated transition (~60 FPS)
+const FRAME: Duration = Duration::from_millis(16);
+
+/// Base of the exponential curve, a perceived 50% maps to ~9% of the raw brightness
+const EXPONENTIAL_BASE: f64 = 100.0;
+
+#[derive(Serialize, Deserialize, Clone)]
 struct Device {
     name: String,
+    /// Perceived brightness (in %), according to the configured curve
     brightness: u32,
+    /// Brightness of the device (in %)
+    #[serde(skip)]
+    raw: u32,
+}
+
+impl Device {
+    fn new(name: String, raw: u32, config: &BrightnessConfiguration) -> Self {
+        Self {
+            name,
+            brightness: config.curve.to_perceived(raw),
+            raw,
+        }
+    }
 }
 
-pub(crate) struct BrightnessCtl {
-    devices: Vec<(Device, BrightnessDevice)>,
+impl Curve {
+    fn to_raw(self, perceived: u32) -> u32 {
+        let perceived = perceived.min(100) as f64 / 100.0;
+        let raw = match self {
+            Curve::Linear => perceived,
+            Curve::Exponential => {
+                (EXPONENTIAL_BASE.powf(perceived) - 1.0) / (EXPONENTIAL_BASE - 1.0)
+            }
+            Curve::Gamma(gamma) => perceived.powf(gamma),
+        };
+        (raw * 100.0).round() as u32
+    }
+
+    fn to_perceived(self, raw: u32) -> u32 {
+        let raw = raw.min(100) as f64 / 100.0;
+        let perceived = match self {
+            Curve::Linear => raw,
+            Curve::Exponential => {
+                (1.0 + raw * (EXPONENTIAL_BASE - 1.0)).ln() / EXPONENTIAL_BASE.ln()
+            }
+            Curve::Gamma(gamma) => raw.powf(1.0 / gamma),
+        };
+        (perceived * 100.0).round() as u32
+    }
+}
+
+/// Reads and writes the raw brightness (in %) of a device, blocking until it is applied
+pub(crate) trait Controller: Send + 'static {
+    fn current(&self) -> Result<u32, brightness::Error>;
+    fn set(&mut self, raw: u32) -> Result<(), brightness::Error>;
 }
 
-#[derive(Serialize)]
+impl Controller for BrightnessDevice {
+    fn current(&self) -> Result<u32, brightness::Error> {
+        Brightness::get(self)
+    }
+
+    fn set(&mut self, raw: u32) -> Result<(), brightness::Error> {
+        Brightness::set(self, raw)
+    }
+}
+
+pub(crate) struct BrightnessCtl<C = BrightnessDevice> {
+    config: BrightnessConfiguration,
+    devices: Vec<(Device, C)>,
+}
+
+#[derive(Serialize, Deserialize)]
 pub(crate) struct BrightnessSettings {
     devices: Vec<Device>,
 }
 
-impl From<&BrightnessCtl> for BrightnessSettings {
-    fn from(val: &BrightnessCtl) -> Self {
+impl<C> From<&BrightnessCtl<C>> for BrightnessSettings {
+    fn from(val: &BrightnessCtl<C>) -> Self {
         BrightnessSettings {
             devices: val.devices.iter().map(|x| x.0.clone()).collect(),
         }
     }
 }
 
-impl From<BrightnessCtl> for BrightnessSettings {
-    fn from(val: BrightnessCtl) -> Self {
+impl<C> From<BrightnessCtl<C>> for BrightnessSettings {
+    fn from(val: BrightnessCtl<C>) -> Self {
         BrightnessSettings {
             devices: val.devices.iter().map(|x| x.0.clone()).collect(),
         }
     }
 }
 
-impl Changeable<u32> for BrightnessCtl {
-    fn change(&mut self, change: Change<u32>) -> Result<(), GlueError> {
-        for (device, controller) in self.devices.iter() {
-            let mut brightness = device.brightness;
-            brightness = match change {
-                Change::Add(update) => (brightness + update).min(100),
-                Change::Sub(div) => brightness.saturating_sub(div),
-                Change::Absolute(value) => (value).min(100),
-            };
-            controller
-                .set(brightness)
-                .map_err(|err| GlueError::Brightness(BrightnessError::Brightness(err)))?;
+impl BrightnessSettings {
+    /// Settings of devices read by the daemon, with raw brightness values (in %)
+    pub(crate) fn from_raw(devices: &[(String, u32)], config: &BrightnessConfiguration) -> Self {
+        Self {
+            devices: devices
+                .iter()
+                .map(|(name, raw)| Device::new(name.clone(), *raw, config))
+                .collect(),
         }
-        self.update();
-        Ok(())
     }
 }
 
+/// Animation of the daemon, which is canceled by the next brightness request
+#[derive(Debug)]
+pub(crate) struct Transition {
+    handle: JoinHandle<()>,
+    targets: Vec<(String, u32)>,
+}
+
+/// Running animation, shared by the brightness requests of the daemon
+pub(crate) type Transitions = Arc<Mutex<Option<Transition>>>;
+
 impl BrightnessCtl {
-    fn new() -> Self {
-        Self {
-            devices: brightness_devices()
-                .filter_map(|x| match x {
-                    Ok(device) => {
-                        let name = match device.device_name() {
-                            Ok(name) => name,
-                            Err(_) => return None,
-                        };
-                        let brightness = match device.get() {
-                            Ok(brightness) => brightness,
-                            Err(_) => return None,
-                        };
-                        Some((Device { name, brightness }, device))
-                    }
-                    Err(_) => None,
-                })
-                .collect(),
+    /// Loads the devices selected by `device` (a name or glob), or by the
+    /// `[brightness]` configuration if no device is given. A `device` matching
+    /// nothing is an error.
+    fn new(config: &Configuration, device: Option<&str>) -> Result<Self, GlueError> {
+        let ctl = Self::load(config, |name| match device {
+            Some(pattern) => glob_match(pattern, name),
+            None => config.brightness.selects(name),
+        });
+        match device {
+            Some(pattern) if ctl.devices.is_empty() => Err(GlueError::Brightness(
+                BrightnessError::NoDevice(pattern.to_string()),
+            )),
+            _ => Ok(ctl),
         }
     }
 
-    fn update(&self) {
-        eww_update(EwwVariable::Brightness(self.into())).unwrap();
+    fn load(config: &Configuration, selects: impl Fn(&str) -> bool) -> Self {
+        let config = config.brightness.clone();
+        let devices = brightness_devices()
+            .filter_map(|x| match x {
+                Ok(device) => {
+                    let name = match device.device_name() {
+                        Ok(name) => name,
+                        Err(_) => return None,
+                    };
+                    let raw = match device.get() {
+                        Ok(brightness) => brightness,
+                        Err(_) => return None,
+                    };
+                    Some((Device::new(name, raw, &config), device))
+                }
+                Err(_) => None,
+            })
+            .filter(|(x, _)| selects(&x.name))
+            .collect();
+        Self { config, devices }
     }
 
-    pub fn set(value: u32) -> Result<(), GlueError> {
-        Self::new().change(Change::Absolute(value))?;
-        Ok(())
+    fn target(config: &BrightnessConfiguration, device: &Device, change: Change<u32>) -> u32 {
+        let min = config.min.min(100);
+        let raw = match change {
+            Change::Add(update) => {
+                let raw = config.curve.to_raw(device.brightness + update);
+                // Steep curves round small steps away, the device has to move nonetheless
+                match raw <= device.raw && update > 0 {
+                    true => device.raw + 1,
+                    false => raw,
+                }
+            }
+            Change::Sub(div) => {
+                let raw = config.curve.to_raw(device.brightness.saturating_sub(div));
+                match raw >= device.raw && div > 0 {
+                    true => device.raw.saturating_sub(1),
+                    false => raw,
+                }
+            }
+            Change::Absolute(value) => config.curve.to_raw(value),
+        };
+        raw.clamp(min, 100)
     }
 
-    pub fn get() -> Result<(), GlueError> {
+    fn print(self) -> Result<(), GlueError> {
         print!(
             "{}",
-            serde_json::to_string(&Into::<BrightnessSettings>::into(Self::new())).map_err(
-                |err| { GlueError::Brightness(BrightnessError::Serialization(err.to_string())) }
-            )?
+            serde_json::to_string(&Into::<BrightnessSettings>::into(self)).map_err(|err| {
+                GlueError::Brightness(BrightnessError::Serialization(err.to_string()))
+            })?
         );
-
         Ok(())
     }
+
+    pub fn get(config: &Configuration, device: Option<String>) -> Result<(), GlueError> {
+        Self::new(config, device.as_deref())?.print()
+    }
+
+    /// Lists all devices, regardless of the configured selection
+    pub fn devices(config: &Configuration) -> Result<(), GlueError> {
+        Self::load(config, |_| true).print()
+    }
 }
 
-impl FunctionKey for BrightnessCtl {
-    fn increase() -> Result<(), GlueError> {
-        Self::new().change(Change::Add(5))?;
+impl<C: Controller> BrightnessCtl<C> {
+    /// Raw target brightness of every device. The change is done in perceived space,
+    /// increase/decrease use the step size of the device and continue from the `previous`
+    /// target of a device instead of its current brightness.
+    fn targets(&self, request: &commands::Brightness, previous: &[(String, u32)]) -> Vec<u32> {
+        self.devices
+            .iter()
+            .map(|(device, _)| {
+                let device = match previous.iter().find(|x| x.0 == device.name) {
+                    Some((_, target)) => &Device::new(device.name.clone(), *target, &self.config),
+                    None => device,
+                };
+                let change = match request {
+                    commands::Brightness::Set { percent, .. } => Change::Absolute(*percent),
+                    commands::Brightness::Increase { .. } => {
+                        Change::Add(self.config.step(&device.name))
+                    }
+                    commands::Brightness::Decrease { .. } => {
+                        Change::Sub(self.config.step(&device.name))
+                    }
+                };
+                BrightnessCtl::target(&self.config, device, change)
+            })
+            .collect()
+    }
+
+    fn settings(&self, targets: &[u32]) -> BrightnessSettings {
+        BrightnessSettings {
+            devices: self
+                .devices
+                .iter()
+                .zip(targets)
+                .map(|((device, _), target)| {
+                    Device::new(device.name.clone(), *target, &self.config)
+                })
+                .collect(),
+        }
+    }
+
+    fn set_targets(&mut self, targets: &[u32]) -> Result<(), GlueError> {
+        for ((device, controller), target) in self.devices.iter_mut().zip(targets) {
+            controller
+                .set(*target)
+                .map_err(|err| GlueError::Brightness(BrightnessError::Brightness(err)))?;
+            *device = Device::new(device.name.clone(), *target, &self.config);
+        }
+        self.update();
         Ok(())
     }
 
-    fn decrease() -> Result<(), GlueError> {
-        Self::new().change(Change::Sub(5))?;
+    /// Reads the brightness the devices report now
+    fn read(&mut self) {
+        for (device, controller) in self.devices.iter_mut() {
+            match controller.current() {
+                Ok(raw) => *device = Device::new(device.name.clone(), raw, &self.config),
+                Err(err) => error!("Unable to read the brightness of {}: {err}", device.name),
+            }
+        }
+    }
+
+    /// Writes one frame of an animation, skipping devices which already have their value
+    fn write(&mut self, values: &[u32]) -> Result<(), GlueError> {
+        for ((device, controller), value) in self.devices.iter_mut().zip(values) {
+            if *value == device.raw {
+                continue;
+            }
+            controller
+                .set(*value)
+                .map_err(|err| GlueError::Brightness(BrightnessError::Brightness(err)))?;
+            device.raw = *value;
+        }
         Ok(())
     }
+
+    /// Moves all devices towards their targets in evenly spaced steps over `duration`.
+    /// The writes run on the blocking pool, an aborted animation stops after the current frame.
+    async fn animate(mut self, targets: Vec<u32>, duration: Duration) {
+        let starts = self
+            .devices
+            .iter()
+            .map(|(device, _)| device.raw)
+            .collect::<Vec<_>>();
+        let distance = starts
+            .iter()
+            .zip(&targets)
+            .map(|(start, target)| start.abs_diff(*target))
+            .max()
+            .unwrap_or_default();
+        let frames = (duration.as_millis() / FRAME.as_millis()) as u32;
+        let steps = distance.min(frames).max(1);
+        let mut ticker = interval(duration / steps);
+        ticker.tick().await;
+        for step in 1..=steps {
+            ticker.tick().await;
+            let values = starts
+                .iter()
+                .zip(&targets)
+                .map(|(start, target)| {
+                    (*start as i64 + (*target as i64 - *start as i64) * step as i64 / steps as i64)
+                        as u32
+                })
+                .collect::<Vec<_>>();
+            let frame =
+                tokio::task::spawn_blocking(move || self.write(&values).map(|()| self)).await;
+            self = match frame {
+                Ok(Ok(ctl)) => ctl,
+                Ok(Err(err)) => {
+                    error!("Unable to animate the brightness: {err}");
+                    return;
+                }
+                Err(err) => {
+                    error!("Brightness animation failed: {err}");
+                    return;
+                }
+            };
+        }
+        let _ = tokio::task::spawn_blocking(move || self.update()).await;
+    }
+
+    /// The bar always shows the default selection, even if a single device was changed
+    fn update(&self) {
+        let settings = BrightnessSettings {
+            devices: brightness_devices()
+                .flatten()
+                .filter_map(|device| {
+                    let name = device.device_name().ok()?;
+                    let raw = device.get().ok()?;
+                    Some(Device::new(name, raw, &self.config))
+                })
+                .filter(|device| self.config.selects(&device.name))
+                .collect(),
+        };
+        if let Err(err) = eww_update(EwwVariable::Brightness(settings)) {
+            error!("Unable to update EWW: {err}");
+        }
+    }
+}
+
+/// Forwards a brightness change to the daemon, which animates the transition.
+/// Without a running daemon the brightness is changed directly.
+pub fn client(request: commands::Brightness, config: &Configuration) -> Result<(), GlueError> {
+    match daemon::client(commands::Command::Brightness(request.clone())) {
+        Ok(message) => {
+            serde_json::from_slice::<Result<BrightnessSettings, String>>(&message)
+                .map_err(|err| {
+                    GlueError::Brightness(BrightnessError::Serialization(err.to_string()))
+                })?
+                .map_err(|err| GlueError::Brightness(BrightnessError::Daemon(err)))?;
+            Ok(())
+        }
+        Err(err) => {
+            info!("Changing brightness without the daemon: {err}");
+            let mut ctl = BrightnessCtl::new(config, request.device())?;
+            let targets = ctl.targets(&request, &[]);
+            ctl.set_targets(&targets)
+        }
+    }
+}
+
+/// Lowers the selected devices to the perceived `percent`, returning their previous raw values
+pub(crate) fn dim(config: &Configuration, percent: u32) -> Result<Vec<(String, u32)>, GlueError> {
+    let mut ctl = BrightnessCtl::new(config, None)?;
+    let limit = ctl.config.curve.to_raw(percent);
+    let saved = ctl
+        .devices
+        .iter()
+        .map(|(device, _)| (device.name.clone(), device.raw))
+        .collect::<Vec<_>>();
+    let targets = saved
+        .iter()
+        .map(|(_, raw)| (*raw).min(limit))
+        .collect::<Vec<_>>();
+    ctl.set_targets(&targets)?;
+    Ok(saved)
+}
+
+/// Restores the raw values saved by `dim`
+pub(crate) fn restore(config: &Configuration, saved: &[(String, u32)]) -> Result<(), GlueError> {
+    let mut ctl = BrightnessCtl::new(config, None)?;
+    let targets = ctl
+        .devices
+        .iter()
+        .map(
+            |(device, _)| match saved.iter().find(|x| x.0 == device.name) {
+                Some((_, raw)) => *raw,
+                None => device.raw,
+            },
+        )
+        .collect::<Vec<_>>();
+    ctl.set_targets(&targets)
+}
+
+/// Applies a brightness request inside the daemon, canceling the previous animation.
+/// The devices are read and written on the blocking pool, concurrent requests wait
+/// for the lock of `transitions` only.
+pub(crate) async fn transition(
+    request: commands::Brightness,
+    config: Arc<Configuration>,
+    transitions: &Transitions,
+) -> Result<BrightnessSettings, GlueError> {
+    let mut current = transitions.lock().await;
+    let device = request.device().map(str::to_string);
+    let ctl = {
+        let config = config.clone();
+        tokio::task::spawn_blocking(move || BrightnessCtl::new(&config, device.as_deref()))
+            .await
+            .map_err(|err| GlueError::Brightness(BrightnessError::Task(err.to_string())))??
+    };
+    let (settings, next) =
+        apply(ctl, &request, current.take(), config.brightness.transition).await?;
+    *current = next;
+    Ok(settings)
+}
+
+/// Changes the devices of `ctl`, animated over `duration` if set
+async fn apply<C: Controller>(
+    mut ctl: BrightnessCtl<C>,
+    request: &commands::Brightness,
+    previous: Option<Transition>,
+    duration: Option<Duration>,
+) -> Result<(BrightnessSettings, Option<Transition>), GlueError> {
+    let mut heading = Vec::new();
+    if let Some(previous) = previous {
+        if !previous.handle.is_finished() {
+            previous.handle.abort();
+            let _ = previous.handle.await;
+            // Relative changes continue from where the canceled animation was heading,
+            // the next animation starts from where it stopped
+            heading = previous.targets;
+            ctl = tokio::task::spawn_blocking(move || {
+                ctl.read();
+                ctl
+            })
+            .await
+            .map_err(|err| GlueError::Brightness(BrightnessError::Task(err.to_string())))?;
+        }
+    }
+    let targets = ctl.targets(request, &heading);
+    let settings = ctl.settings(&targets);
+    match duration {
+        Some(duration) if !duration.is_zero() => {
+            let names = ctl
+                .devices
+                .iter()
+                .zip(&targets)
+                .map(|((device, _), target)| (device.name.clone(), *target))
+                .collect();
+            let transition = Transition {
+                handle: tokio::spawn(ctl.animate(targets, duration)),
+                targets: names,
+            };
+            Ok((settings, Some(transition)))
+        }
+        _ => {
+            tokio::task::spawn_blocking(move || ctl.set_targets(&targets))
+                .await
+                .map_err(|err| GlueError::Brightness(BrightnessError::Task(err.to_string())))??;
+            Ok((settings, None))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn device(raw: u32, config: &BrightnessConfiguration) -> Device {
+        Device::new("intel_backlight".to_string(), raw, config)
+    }
+
+    /// Records the written values
+    #[derive(Clone, Default)]
+    struct FakeController(Arc<std::sync::Mutex<Vec<u32>>>);
+
+    impl Controller for FakeController {
+        fn current(&self) -> Result<u32, brightness::Error> {
+            Ok(self.0.lock().unwrap().last().copied().unwrap_or_default())
+        }
+
+        fn set(&mut self, raw: u32) -> Result<(), brightness::Error> {
+            self.0.lock().unwrap().push(raw);
+            Ok(())
+        }
+    }
+
+    impl FakeController {
+        fn ctl(&self, config: &BrightnessConfiguration) -> BrightnessCtl<FakeController> {
+            let raw = self.0.lock().unwrap().last().copied().unwrap_or_default();
+            BrightnessCtl {
+                config: config.clone(),
+                devices: vec![(device(raw, config), self.clone())],
+            }
+        }
+    }
+
+    #[test]
+    fn test_curve_roundtrip() {
+        for curve in [Curve::Linear, Curve::Exponential, Curve::Gamma(2.2)] {
+            assert_eq!(curve.to_raw(0), 0);
+            assert_eq!(curve.to_raw(100), 100);
+            assert_eq!(curve.to_perceived(curve.to_raw(60)), 60);
+        }
+        assert!(Curve::Gamma(2.2).to_raw(50) < 50);
+        assert!(Curve::Exponential.to_raw(50) < Curve::Gamma(2.2).to_raw(50));
+    }
+
+    #[test]
+    fn test_target_is_perceptual() {
+        let config = BrightnessConfiguration {
+            curve: Curve::Gamma(2.0),
+            ..Default::default()
+        };
+        let device = device(25, &config);
+        assert_eq!(device.brightness, 50);
+        assert_eq!(BrightnessCtl::target(&config, &device, Change::Add(10)), 36);
+        assert_eq!(BrightnessCtl::target(&config, &device, Change::Sub(10)), 16);
+    }
+
+    #[test]
+    fn test_target_always_moves() {
+        let config = BrightnessConfiguration {
+            curve: Curve::Exponential,
+            min: 0,
+            ..Default::default()
+        };
+        let device = device(0, &config);
+        assert_eq!(BrightnessCtl::target(&config, &device, Change::Add(1)), 1);
+    }
+
+    #[test]
+    fn test_target_never_below_min() {
+        let config = BrightnessConfiguration {
+            min: 5,
+            ..Default::default()
+        };
+        let device = device(8, &config);
+        assert_eq!(BrightnessCtl::target(&config, &device, Change::Sub(5)), 5);
+        assert_eq!(
+            BrightnessCtl::target(&config, &device, Change::Absolute(0)),
+            5
+        );
+    }
+
+    #[tokio::test]
+    async fn test_transition_canceled_by_next_request() {
+        let config = BrightnessConfiguration {
+            min: 0,
+            ..Default::default()
+        };
+        let controller = FakeController::default();
+        let duration = Some(Duration::from_millis(200));
+        let set = commands::Brightness::Set {
+            percent: 100,
+            device: None,
+        };
+        let (settings, first) = apply(controller.ctl(&config), &set, None, duration)
+            .await
+            .unwrap();
+        assert_eq!(settings.devices[0].brightness, 100);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        // Continues from the target of the canceled animation
+        let decrease = commands::Brightness::Decrease { device: None };
+        let (settings, second) = apply(controller.ctl(&config), &decrease, first, duration)
+            .await
+            .unwrap();
+        assert_eq!(settings.devices[0].brightness, 95);
+        second.unwrap().handle.await.unwrap();
+        let written = controller.0.lock().unwrap().clone();
+        assert_eq!(written.last(), Some(&95));
+        assert!(!written.contains(&100));
+    }
+
+    #[tokio::test]
+    async fn test_canceled_transition_continues_smoothly() {
+        let config = BrightnessConfiguration {
+            min: 0,
+            ..Default::default()
+        };
+        let controller = FakeController::default();
+        let duration = Some(Duration::from_millis(200));
+        let set = commands::Brightness::Set {
+            percent: 100,
+            device: None,
+        };
+        let (_, first) = apply(controller.ctl(&config), &set, None, duration)
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        // Loaded before the request cancels the animation, like the daemon does
+        let ctl = controller.ctl(&config);
+        tokio::time::sleep(Duration::from_millis(40)).await;
+        let decrease = commands::Brightness::Decrease { device: None };
+        let (_, second) = apply(ctl, &decrease, first, duration).await.unwrap();
+        let interrupted = controller.0.lock().unwrap().len();
+        second.unwrap().handle.await.unwrap();
+        let written = controller.0.lock().unwrap().clone();
+        let (last, next) = (written[interrupted - 1], written[interrupted]);
+        assert!(last < 95, "interrupted at {last}");
+        assert!(next.abs_diff(last) <= 10, "jumped from {last} to {next}");
+    }
+
+    #[tokio::test]
+    async fn test_transition_without_duration_is_instant() {
+        let config = BrightnessConfiguration::default();
+        let controller = FakeController::default();
+        let set = commands::Brightness::Set {
+            percent: 40,
+            device: None,
+        };
+        let (_, transition) = apply(controller.ctl(&config), &set, None, None)
+            .await
+            .unwrap();
+        assert!(transition.is_none());
+        assert_eq!(*controller.0.lock().unwrap(), vec![40]);
+    }
 }
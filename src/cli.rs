@@ -1,5 +1,7 @@
 use clap::{Parser, Subcommand};
 
+use crate::configuration::WorkspaceFormat;
+
 #[derive(Parser)]
 pub struct Cli {
     /// Turn debugging information on
@@ -21,6 +23,12 @@ pub enum Command {
     Workspace {
         #[arg(default_value_t = 5)]
         default_spaces: usize,
+        /// Overrides `hyprland.format`
+        #[arg(long)]
+        format: Option<WorkspaceFormat>,
+        /// Only the workspaces of the monitor, e.g. `DP-1`
+        #[arg(long)]
+        monitor: Option<String>,
         #[command(subcommand)]
         command: Option<WorkspaceCommand>,
     },
@@ -36,10 +44,30 @@ pub enum Command {
         #[command(subcommand)]
         command: BatteryCommand,
     },
+    Power {
+        #[command(subcommand)]
+        command: PowerCommand,
+    },
     Brightness {
         #[command(subcommand)]
         command: BrightnessCommand,
     },
+    KbdBacklight {
+        #[command(subcommand)]
+        command: KbdBacklightCommand,
+    },
+    Night {
+        #[command(subcommand)]
+        command: NightCommand,
+    },
+    Window {
+        #[command(subcommand)]
+        command: WindowCommand,
+    },
+    Keyboard {
+        #[command(subcommand)]
+        command: KeyboardCommand,
+    },
     Start {},
     WakeUp {
         #[arg(short, long)]
@@ -61,6 +89,9 @@ pub enum WorkspaceCommand {
     Update {
         #[arg(default_value_t = 5)]
         default_spaces: usize,
+        /// Overrides `hyprland.format`
+        #[arg(long)]
+        format: Option<WorkspaceFormat>,
     },
 }
 
@@ -82,22 +113,129 @@ pub enum MicCommand {
 #[derive(Subcommand)]
 pub enum BatteryCommand {
     Get,
+    History {
+        /// Only show samples within this duration, e.g. `24h` or `7days`
+        #[arg(long, default_value = "24h")]
+        since: humantime::Duration,
+    },
+    Health,
+    Limit {
+        #[command(subcommand)]
+        command: LimitCommand,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum LimitCommand {
+    Get,
+    /// Set `<start> <end>`, or only `<end>` for batteries without a start threshold
+    Set {
+        /// `[start] end` (in %)
+        #[arg(num_args = 1..=2, required = true, value_name = "THRESHOLD")]
+        thresholds: Vec<u8>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum PowerCommand {
+    Ac,
 }
 
 #[derive(Subcommand)]
 pub enum CoffeeCommand {
-    Drink,
-    Relax,
+    Drink {
+        /// Relax again after this duration, e.g. `45m`
+        #[arg(long = "for", conflicts_with = "until")]
+        duration: Option<humantime::Duration>,
+        /// Relax again at this local time, e.g. `17:30`
+        #[arg(long)]
+        until: Option<chrono::NaiveTime>,
+        /// Named inhibitor, which is reference counted and released separately
+        #[arg(long)]
+        reason: Option<String>,
+        /// Release the inhibitor when this process exits
+        #[arg(long)]
+        owner: Option<u32>,
+    },
+    Relax {
+        /// Release one inhibitor of this reason instead of the manual coffee
+        #[arg(long)]
+        reason: Option<String>,
+    },
+    /// Stay awake while the command runs, e.g. `glue coffee run -- cargo build`
+    Run {
+        /// Reason of the inhibitor, defaults to the program name
+        #[arg(long)]
+        reason: Option<String>,
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true, required = true)]
+        command: Vec<String>,
+    },
     Toggle,
     Get,
 }
 
 #[derive(Subcommand)]
 pub enum BrightnessCommand {
+    Get {
+        /// Device name or glob, e.g. `intel_backlight` or `ddcci*`
+        #[arg(short, long)]
+        device: Option<String>,
+    },
+    Set {
+        percent: u32,
+        /// Device name or glob, e.g. `intel_backlight` or `ddcci*`
+        #[arg(short, long)]
+        device: Option<String>,
+    },
+    Increase {
+        /// Device name or glob, e.g. `intel_backlight` or `ddcci*`
+        #[arg(short, long)]
+        device: Option<String>,
+    },
+    Decrease {
+        /// Device name or glob, e.g. `intel_backlight` or `ddcci*`
+        #[arg(short, long)]
+        device: Option<String>,
+    },
+    Devices,
+}
+
+#[derive(Subcommand)]
+pub enum KbdBacklightCommand {
     Get,
-    Set { percent: u32 },
+    Set { level: u32 },
     Increase,
     Decrease,
+    Toggle,
+}
+
+#[derive(Subcommand)]
+pub enum NightCommand {
+    On,
+    Off,
+    Toggle,
+    /// Follow the configured schedule
+    Auto,
+    Set {
+        kelvin: u32,
+    },
+    Get,
+}
+
+#[derive(Subcommand)]
+pub enum WindowCommand {
+    /// Print the focused window as JSON
+    Get,
+}
+
+#[derive(Subcommand)]
+pub enum KeyboardCommand {
+    /// Print the layouts of the main keyboard as JSON
+    Get,
+    /// Switch the main keyboard to its next layout
+    Next,
+    /// Switch the main keyboard to a layout, e.g. `de`, or its index
+    Set { layout: String },
 }
 
 #[derive(Subcommand)]
@@ -1,9 +1,29 @@
+use std::os::unix::process::ExitStatusExt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
 use crate::{
-    commands::Coffee, configuration::Configuration, daemon, error::CoffeeError,
-    utils::CancelableTimer, wayland::WaylandIdle, DaemonState, IdleState,
+    commands::Coffee,
+    configuration::{Coffee as CoffeeConfiguration, Configuration},
+    daemon,
+    error::{CoffeeError, GlueError},
+    eww::{self, eww_update},
+    monitor::Monitor,
+    utils::{state_dir, CancelableTimer},
+    wayland::WaylandIdle,
+    DaemonState, IdleState,
 };
-use log::error;
-use serde::Serialize;
+use async_trait::async_trait;
+use chrono::NaiveTime;
+use log::{error, info, warn};
+use notify_rust::{Notification, Timeout};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+
+/// Reason of the coffee requested without `--reason`
+pub const MANUAL: &str = "manual";
 
 /*
 Requires the daemon to hold the file descriper to block the system
@@ -14,13 +34,38 @@ via a socket.
 #[derive(Serialize)]
 pub struct CoffeeResponse {
     inhibited: bool,
+    manual: bool,
+    reasons: Vec<Reason>,
+    rules: Vec<String>,
     icon: char,
+    /// Unix timestamp when a timed coffee is released
+    until: Option<u64>,
+    /// Seconds until a timed coffee is released
+    remaining: Option<u64>,
+    /// Why the coffee is not enforced, e.g. the compositor restarted
+    degraded: Option<String>,
 }
 
 impl CoffeeResponse {
     pub fn new(configuration: &Configuration, state: &IdleState) -> Self {
+        let timestamp = |time: SystemTime| {
+            time.duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs()
+        };
         Self {
             inhibited: state.inhibited,
+            manual: state.manual,
+            reasons: state.reasons.clone(),
+            rules: state.rules.clone(),
+            until: state.until.map(timestamp),
+            remaining: state.until.map(|until| {
+                until
+                    .duration_since(SystemTime::now())
+                    .unwrap_or_default()
+                    .as_secs()
+            }),
+            degraded: state.degraded.clone(),
             icon: if state.inhibited {
                 configuration.coffee.coffee
             } else {
@@ -30,6 +75,213 @@ impl CoffeeResponse {
     }
 }
 
+/// Active inhibitors of one reason
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Reason {
+    reason: String,
+    count: usize,
+    owners: Vec<u32>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Inhibitor {
+    #[serde(skip)]
+    id: u64,
+    reason: String,
+    owner: Option<u32>,
+    /// Start time of the owner, which tells it apart from a later process with the same pid
+    #[serde(default)]
+    started: Option<u64>,
+    until: Option<SystemTime>,
+    #[serde(skip)]
+    expiry: Option<JoinHandle<()>>,
+}
+
+/// Daemon state, which survives restarts
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct SavedState {
+    /// Boot of the saved owners, pids and start times are only valid within it
+    #[serde(default)]
+    boot_id: Option<String>,
+    coffee: Vec<Inhibitor>,
+}
+
+fn state_file() -> PathBuf {
+    state_dir().join("state.json")
+}
+
+impl Drop for Inhibitor {
+    fn drop(&mut self) {
+        if let Some(expiry) = self.expiry.take() {
+            expiry.abort();
+        }
+    }
+}
+
+/// Requested coffees, reference counted per reason
+#[derive(Debug, Default)]
+pub(crate) struct Inhibitors {
+    next_id: u64,
+    list: Vec<Inhibitor>,
+}
+
+impl Inhibitors {
+    /// Adds an inhibitor and returns its id. The manual coffee without owner
+    /// exists only once, drinking it again only replaces its expiry.
+    fn add(&mut self, reason: String, owner: Option<u32>, duration: Option<Duration>) -> u64 {
+        let until = duration.map(|duration| SystemTime::now() + duration);
+        if reason == MANUAL && owner.is_none() {
+            if let Some(manual) = self
+                .list
+                .iter_mut()
+                .find(|x| x.reason == MANUAL && x.owner.is_none())
+            {
+                if let Some(expiry) = manual.expiry.take() {
+                    expiry.abort();
+                }
+                manual.until = until;
+                return manual.id;
+            }
+        }
+        self.next_id += 1;
+        self.list.push(Inhibitor {
+            id: self.next_id,
+            reason,
+            owner,
+            started: owner.and_then(start_time),
+            until,
+            expiry: None,
+        });
+        self.next_id
+    }
+
+    fn set_expiry(&mut self, id: u64, expiry: JoinHandle<()>) {
+        match self.list.iter_mut().find(|x| x.id == id) {
+            Some(inhibitor) => inhibitor.expiry = Some(expiry),
+            None => expiry.abort(),
+        }
+    }
+
+    /// Removes the most recent inhibitor of `reason` without owner, owned inhibitors are
+    /// released by their owner only
+    fn remove_reason(&mut self, reason: &str) -> bool {
+        match self
+            .list
+            .iter()
+            .rposition(|x| x.reason == reason && x.owner.is_none())
+        {
+            Some(index) => {
+                self.list.remove(index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Removes an inhibitor from its own expiry task, which must not be aborted
+    fn expired(&mut self, id: u64) -> bool {
+        match self.list.iter().position(|x| x.id == id) {
+            Some(index) => {
+                let _ = self.list[index].expiry.take();
+                self.list.remove(index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn remove_owner(&mut self, pid: u32) -> bool {
+        let count = self.list.len();
+        self.list.retain(|x| x.owner != Some(pid));
+        count != self.list.len()
+    }
+
+    /// Removes the inhibitors whose owner is no longer `alive` and returns the exited pids
+    fn remove_exited(&mut self, alive: impl Fn(u32, Option<u64>) -> bool) -> Vec<u32> {
+        let mut exited = Vec::new();
+        self.list.retain(|x| match x.owner {
+            Some(pid) if !alive(pid, x.started) => {
+                exited.push(pid);
+                false
+            }
+            _ => true,
+        });
+        exited.sort_unstable();
+        exited.dedup();
+        exited
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+
+    /// The coffee of the user, `manual` without owner
+    pub(crate) fn manual(&self) -> bool {
+        self.list
+            .iter()
+            .any(|x| x.reason == MANUAL && x.owner.is_none())
+    }
+
+    pub(crate) fn reasons(&self) -> Vec<Reason> {
+        let mut reasons: Vec<Reason> = Vec::new();
+        for inhibitor in &self.list {
+            let index = match reasons.iter().position(|x| x.reason == inhibitor.reason) {
+                Some(index) => index,
+                None => {
+                    reasons.push(Reason {
+                        reason: inhibitor.reason.clone(),
+                        count: 0,
+                        owners: Vec::new(),
+                    });
+                    reasons.len() - 1
+                }
+            };
+            reasons[index].count += 1;
+            reasons[index].owners.extend(inhibitor.owner);
+        }
+        reasons
+    }
+
+    fn save(&self, path: &Path) {
+        let state = serde_json::to_vec(&SavedState {
+            boot_id: boot_id(),
+            coffee: self
+                .list
+                .iter()
+                .map(|x| Inhibitor {
+                    id: x.id,
+                    reason: x.reason.clone(),
+                    owner: x.owner,
+                    started: x.started,
+                    until: x.until,
+                    expiry: None,
+                })
+                .collect(),
+        })
+        .unwrap_or_default();
+        let result = path
+            .parent()
+            .map_or(Ok(()), std::fs::create_dir_all)
+            .and_then(|()| std::fs::write(path, state));
+        if let Err(err) = result {
+            error!(
+                "Unable to save the coffee state to {}: {err}",
+                path.display()
+            );
+        }
+    }
+
+    /// End of the coffee, if every inhibitor is timed
+    pub(crate) fn until(&self) -> Option<SystemTime> {
+        self.list
+            .iter()
+            .map(|x| x.until)
+            .collect::<Option<Vec<_>>>()?
+            .into_iter()
+            .max()
+    }
+}
+
 /// IPC coffee client, which forwards commands to the daemon
 pub fn client(command: Coffee, configuration: &Configuration) -> Result<(), CoffeeError> {
     let response = daemon::client(command.into()).map_err(CoffeeError::IPCError);
@@ -47,38 +299,699 @@ pub fn client(command: Coffee, configuration: &Configuration) -> Result<(), Coff
         }
     };
     if !message.is_empty() {
-        let state = serde_json::from_slice::<WaylandIdle>(&message).unwrap();
+        let state = serde_json::from_slice::<IdleState>(&message).unwrap();
         println!(
             "{}",
-            serde_json::to_string(&CoffeeResponse::new(configuration, &state.into())).unwrap()
+            serde_json::to_string(&CoffeeResponse::new(configuration, &state)).unwrap()
         );
     }
     Ok(())
 }
 
-pub fn coffeinate(state: &mut DaemonState) -> Result<(), CoffeeError> {
-    state.idle_inhibited = true;
-    state
-        .wayland_idle
-        .inhibit()
-        .map_err(CoffeeError::WaylandError)?;
-    if let Some(notify) = state.notification {
-        let timer = CancelableTimer::new(notify);
-        timer.start();
-        state.idle_notify = Some(timer);
+/// Runs `command` while the daemon keeps the system awake and returns its exit code.
+/// The daemon owns the inhibitor of the child, it is released even if this client is killed.
+pub fn run(command: Vec<String>, reason: Option<String>) -> Result<i32, CoffeeError> {
+    let Some((program, args)) = command.split_first() else {
+        return Ok(0);
+    };
+    let run_error = |err: std::io::Error| CoffeeError::Run(command.join(" "), err.to_string());
+    let mut child = std::process::Command::new(program)
+        .args(args)
+        .spawn()
+        .map_err(run_error)?;
+    let owner = child.id();
+    let reason = reason.unwrap_or_else(|| {
+        Path::new(program)
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| program.clone())
+    });
+    let drink = Coffee::Drink {
+        duration: None,
+        reason: Some(reason),
+        owner: Some(owner),
+    };
+    if let Err(err) = daemon::client(drink.into()) {
+        error!("Running without coffee: {err}");
+    }
+    let status = child.wait().map_err(run_error)?;
+    if let Err(err) = daemon::client(Coffee::Release { owner }.into()) {
+        error!("{err}");
+    }
+    Ok(status
+        .code()
+        .unwrap_or_else(|| 128 + status.signal().unwrap_or_default()))
+}
+
+/// Adds an inhibitor for `reason` (default `manual`), which is released after `duration`
+/// or when the `owner` process exits.
+pub async fn coffeinate(
+    shared: &Arc<Mutex<DaemonState>>,
+    state: &mut DaemonState,
+    config: &Arc<Configuration>,
+    reason: Option<String>,
+    owner: Option<u32>,
+    duration: Option<Duration>,
+) -> Result<(), CoffeeError> {
+    let reason = reason.unwrap_or_else(|| MANUAL.to_string());
+    // The reminder asks about the manual coffee only
+    let first = reason == MANUAL && owner.is_none() && !state.inhibitors.manual();
+    let id = state.inhibitors.add(reason, owner, duration);
+    if let Some(duration) = duration {
+        let expiry = expire(shared, id, duration, config);
+        state.inhibitors.set_expiry(id, expiry);
+    }
+    sync(state).await?;
+    if first {
+        remind(shared, state, config);
+    }
+    Ok(())
+}
+
+/// Releases one inhibitor of `reason` (default `manual`)
+pub async fn decoffeinate(
+    state: &mut DaemonState,
+    reason: Option<&str>,
+) -> Result<(), CoffeeError> {
+    state.inhibitors.remove_reason(reason.unwrap_or(MANUAL));
+    released(state).await
+}
+
+/// Releases all inhibitors of the `owner` process
+pub async fn release_owner(state: &mut DaemonState, owner: u32) -> Result<(), CoffeeError> {
+    if state.inhibitors.remove_owner(owner) {
+        released(state).await?;
     }
     Ok(())
 }
 
-pub fn decoffeinate(state: &mut DaemonState) -> Result<(), CoffeeError> {
-    state.idle_inhibited = false;
+async fn released(state: &mut DaemonState) -> Result<(), CoffeeError> {
+    if !state.inhibitors.manual() {
+        if let Some(notification) = state.idle_notify.take() {
+            notification.cancel();
+        }
+    }
+    sync(state).await
+}
+
+/// Replaces the reasons of the automatic rules, returns whether they changed
+pub async fn set_reasons(
+    state: &mut DaemonState,
+    reasons: Vec<String>,
+) -> Result<bool, CoffeeError> {
+    if state.auto_inhibit == reasons {
+        return Ok(false);
+    }
+    state.auto_inhibit = reasons;
+    sync(state).await?;
+    Ok(true)
+}
+
+/// Holds the inhibitors of the configured backend while any coffee or automatic rule is active
+async fn sync(state: &mut DaemonState) -> Result<(), CoffeeError> {
+    state.inhibitors.save(&state_file());
+    let inhibited = state.inhibited();
+    // A lost compositor must not keep the logind lock from being applied
+    let wayland = match (state.backend.wayland(), inhibited) {
+        (false, _) => Ok(()),
+        (true, true) => state.wayland_idle.inhibit(),
+        (true, false) => state.wayland_idle.release(),
+    };
+    if state.backend.logind() {
+        match inhibited {
+            true => state.logind.inhibit().await?,
+            false => state.logind.release(),
+        }
+    }
+    wayland.map_err(CoffeeError::WaylandError)
+}
+
+fn update(config: &Configuration, state: &DaemonState) {
+    let response = CoffeeResponse::new(config, &IdleState::from(state));
+    if let Err(err) = eww_update(eww::EwwVariable::Coffee(response)) {
+        error!("Unable to update EWW: {err:#?}");
+    }
+}
+
+/// Releases the inhibitor `id` after `duration`
+fn expire(
+    shared: &Arc<Mutex<DaemonState>>,
+    id: u64,
+    duration: Duration,
+    config: &Arc<Configuration>,
+) -> JoinHandle<()> {
+    let shared = shared.clone();
+    let config = config.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(duration).await;
+        let mut state = shared.lock().await;
+        if !state.inhibitors.expired(id) {
+            return;
+        }
+        info!(
+            "Coffee expired after {}",
+            humantime::format_duration(duration)
+        );
+        if let Err(err) = released(&mut state).await {
+            error!("{err}");
+        }
+        update(&config, &state);
+    })
+}
+
+/// Inhibitors of the saved state, which have neither expired nor lost their owner,
+/// with their remaining duration. Owners of another boot are gone as well.
+fn restorable(path: &Path, now: SystemTime) -> Vec<(String, Option<u32>, Option<Duration>)> {
+    let state = match std::fs::read(path) {
+        Ok(content) => serde_json::from_slice::<SavedState>(&content).unwrap_or_else(|err| {
+            error!("Unable to parse the coffee state {}: {err}", path.display());
+            SavedState::default()
+        }),
+        Err(_) => SavedState::default(),
+    };
+    let same_boot = state.boot_id.is_some() && state.boot_id == boot_id();
     state
-        .wayland_idle
-        .release()
-        .map_err(CoffeeError::WaylandError)?;
-    if let Some(notification) = &state.idle_notify {
-        notification.cancel();
+        .coffee
+        .iter()
+        .filter(|x| x.owner.is_none_or(|pid| same_boot && alive(pid, x.started)))
+        .filter_map(|x| match x.until {
+            Some(until) => until
+                .duration_since(now)
+                .ok()
+                .map(|remaining| (x.reason.clone(), x.owner, Some(remaining))),
+            None => Some((x.reason.clone(), x.owner, None)),
+        })
+        .collect()
+}
+
+/// Restores the coffee of the previous daemon and publishes the state to eww
+pub(crate) async fn restore(shared: &Arc<Mutex<DaemonState>>, config: &Arc<Configuration>) {
+    let mut state = shared.lock().await;
+    if config.coffee.restore {
+        for (reason, owner, duration) in restorable(&state_file(), SystemTime::now()) {
+            info!("Restoring coffee {reason}");
+            let result =
+                coffeinate(shared, &mut state, config, Some(reason), owner, duration).await;
+            if let Err(err) = result {
+                error!("{err}");
+            }
+        }
+    }
+    update(config, &state);
+}
+
+/// Whether `pid` runs and, if known, is still the process started at `started`
+fn alive(pid: u32, started: Option<u64>) -> bool {
+    start_time(pid).is_some_and(|time| started.is_none_or(|started| started == time))
+}
+
+/// Start time of a process in clock ticks after boot (field 22 of `/proc/<pid>/stat`)
+fn start_time(pid: u32) -> Option<u64> {
+    let stat =
+        std::fs::read_to_string(Path::new("/proc").join(pid.to_string()).join("stat")).ok()?;
+    // The command name in field 2 may contain spaces and parentheses
+    let (_, fields) = stat.rsplit_once(')')?;
+    fields.split_whitespace().nth(19)?.parse().ok()
+}
+
+fn boot_id() -> Option<String> {
+    std::fs::read_to_string("/proc/sys/kernel/random/boot_id")
+        .ok()
+        .map(|id| id.trim().to_string())
+}
+
+/// Releases the inhibitors of owners which exited
+pub(crate) struct Owners {
+    config: Arc<Configuration>,
+    state: Arc<Mutex<DaemonState>>,
+}
+
+impl Owners {
+    pub(crate) fn new(config: Arc<Configuration>, state: Arc<Mutex<DaemonState>>) -> Self {
+        Self { config, state }
+    }
+}
+
+#[async_trait]
+impl Monitor for Owners {
+    async fn update(&mut self) -> Result<(), GlueError> {
+        let mut state = self.state.lock().await;
+        let exited = state.inhibitors.remove_exited(alive);
+        for pid in &exited {
+            info!("Coffee owner {pid} exited");
+        }
+        if !exited.is_empty() {
+            released(&mut state).await.map_err(GlueError::Coffee)?;
+            update(&self.config, &state);
+        }
+        Ok(())
+    }
+
+    async fn event(&self) {}
+}
+
+const RECONNECT_MIN: Duration = Duration::from_secs(1);
+const RECONNECT_MAX: Duration = Duration::from_secs(60);
+/// Time between two checks of a working connection
+const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Reconnects to the compositor with an exponential backoff, when the connection was lost or
+/// the idle inhibitor globals of the wayland backend were removed, and applies the active
+/// coffee again. Night light and the idle notifications follow the new connection.
+pub(crate) struct Reconnect {
+    config: Arc<Configuration>,
+    state: Arc<Mutex<DaemonState>>,
+    /// Next attempt and the current backoff
+    retry: Option<(Instant, Duration)>,
+    checked: Option<Instant>,
+}
+
+impl Reconnect {
+    pub(crate) fn new(config: Arc<Configuration>, state: Arc<Mutex<DaemonState>>) -> Self {
+        Self {
+            config,
+            state,
+            retry: None,
+            checked: None,
+        }
+    }
+}
+
+#[async_trait]
+impl Monitor for Reconnect {
+    async fn update(&mut self) -> Result<(), GlueError> {
+        let now = Instant::now();
+        let due = match self.retry {
+            Some((next, _)) => now >= next,
+            None => self
+                .checked
+                .is_none_or(|checked| now >= checked + CHECK_INTERVAL),
+        };
+        if !due {
+            return Ok(());
+        }
+        self.checked = Some(now);
+        let (mut client, inhibited, wayland) = {
+            let state = self.state.lock().await;
+            (
+                state.wayland_idle.clone(),
+                state.inhibited(),
+                state.backend.wayland(),
+            )
+        };
+        let reconnect = self.retry.is_some();
+        // The roundtrips block, the state is not locked meanwhile
+        let result = tokio::task::spawn_blocking(move || match reconnect {
+            false => client.check(wayland).map(|()| None),
+            true => client
+                .reconnect(wayland)
+                .and_then(|()| match inhibited && wayland {
+                    true => client.inhibit(),
+                    false => Ok(()),
+                })
+                .map(|()| Some(client)),
+        })
+        .await
+        .map_err(|err| err.to_string())
+        .and_then(|result| result.map_err(|err| err.to_string()));
+        let mut state = self.state.lock().await;
+        match result {
+            Ok(client) => {
+                if let Some(client) = client {
+                    state.wayland_idle = client;
+                    let connection = state.wayland_idle.connection().clone();
+                    state.wayland_connection.send_replace(connection);
+                    // The coffee changed while reconnecting
+                    if state.inhibited() != inhibited {
+                        if let Err(err) = sync(&mut state).await {
+                            error!("{err}");
+                        }
+                    }
+                }
+                if self.retry.take().is_some() {
+                    info!("Wayland connection restored");
+                    if state.degraded.take().is_some() {
+                        update(&self.config, &state);
+                    }
+                }
+            }
+            Err(err) => {
+                let backoff = backoff(self.retry.map(|(_, backoff)| backoff));
+                warn!("Wayland: {err}, reconnecting in {backoff:?}");
+                self.retry = Some((Instant::now() + backoff, backoff));
+                // Only the wayland backend loses the coffee
+                if wayland && state.degraded.is_none() {
+                    state.degraded = Some(err);
+                    update(&self.config, &state);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn event(&self) {}
+}
+
+/// Doubles the previous backoff up to `RECONNECT_MAX`
+fn backoff(previous: Option<Duration>) -> Duration {
+    previous.map_or(RECONNECT_MIN, |backoff| (backoff * 2).min(RECONNECT_MAX))
+}
+
+#[derive(Debug)]
+enum Reminder {
+    Keep,
+    Relax,
+    Ignored,
+    /// The reminder could not be shown, the coffee is kept
+    Failed,
+}
+
+#[derive(Debug, PartialEq)]
+enum FollowUp {
+    /// Asks again after `coffee.notification`
+    Remind,
+    /// Releases the manual coffee, other reasons and owners keep their coffee
+    Relax,
+    Nothing,
+}
+
+impl Reminder {
+    fn follow_up(&self, config: &CoffeeConfiguration) -> FollowUp {
+        match self {
+            Reminder::Keep | Reminder::Failed => FollowUp::Remind,
+            Reminder::Ignored if config.grace.is_none() && config.repeat => FollowUp::Remind,
+            Reminder::Ignored if config.grace.is_none() => FollowUp::Nothing,
+            Reminder::Relax | Reminder::Ignored => FollowUp::Relax,
+        }
+    }
+}
+
+/// Asks after `coffee.notification` whether the manual coffee is still required, replacing a
+/// previous reminder. "Keep awake" asks again after the same duration.
+fn remind(shared: &Arc<Mutex<DaemonState>>, state: &mut DaemonState, config: &Arc<Configuration>) {
+    schedule_reminder(shared, state, config, Duration::ZERO);
+}
+
+fn schedule_reminder(
+    shared: &Arc<Mutex<DaemonState>>,
+    state: &mut DaemonState,
+    config: &Arc<Configuration>,
+    elapsed: Duration,
+) {
+    if let Some(timer) = state.idle_notify.take() {
+        timer.cancel();
+    }
+    let Some(duration) = state.notification else {
+        return;
+    };
+    let timer = CancelableTimer::new(duration);
+    let shared = shared.clone();
+    let config = config.clone();
+    timer.start(move |duration| async move {
+        let elapsed = elapsed + duration;
+        let reminder = ask(elapsed, config.coffee.grace).await;
+        let mut state = shared.lock().await;
+        // Taken first, relaxing would cancel this timer otherwise
         state.idle_notify = None;
+        info!("Coffee reminder: {reminder:?}");
+        match reminder.follow_up(&config.coffee) {
+            FollowUp::Remind => schedule_reminder(&shared, &mut state, &config, elapsed),
+            FollowUp::Relax => {
+                info!("Relaxing the manual coffee after the reminder");
+                if let Err(err) = decoffeinate(&mut state, None).await {
+                    error!("{err}");
+                }
+                update(&config, &state);
+            }
+            FollowUp::Nothing => {}
+        }
+    });
+    state.idle_notify = Some(timer);
+}
+
+/// Shows the actionable reminder, which counts as ignored once `grace` elapsed
+async fn ask(elapsed: Duration, grace: Option<Duration>) -> Reminder {
+    let (shown, mut id) = tokio::sync::oneshot::channel();
+    let mut notification = tokio::task::spawn_blocking(move || {
+        let handle = Notification::new()
+            .summary("Coffee still required?")
+            .body(&format!(
+                "The System is coffeinated since {}",
+                humantime::format_duration(elapsed)
+            ))
+            .action("keep", "Keep awake")
+            .action("relax", "Relax now")
+            .timeout(grace.map(Timeout::from).unwrap_or(Timeout::Default))
+            .show()?;
+        let _ = shown.send(handle.id());
+        let mut reminder = Reminder::Ignored;
+        handle.wait_for_action(|action| {
+            reminder = match action {
+                "keep" => Reminder::Keep,
+                "relax" => Reminder::Relax,
+                _ => Reminder::Ignored,
+            }
+        });
+        Ok::<_, notify_rust::error::Error>(reminder)
+    });
+    let result = match grace {
+        Some(grace) => match tokio::time::timeout(grace, &mut notification).await {
+            Ok(result) => result,
+            Err(_) => {
+                let Ok(id) = id.try_recv() else {
+                    error!("The coffee reminder was not shown within {grace:?}");
+                    return Reminder::Failed;
+                };
+                close(id).await;
+                return Reminder::Ignored;
+            }
+        },
+        None => notification.await,
+    };
+    match result {
+        Ok(Ok(reminder)) => reminder,
+        Ok(Err(err)) => {
+            error!("Unable to send notification: {err:#?}");
+            Reminder::Failed
+        }
+        Err(err) => {
+            error!("Coffee reminder failed: {err}");
+            Reminder::Failed
+        }
+    }
+}
+
+/// Closes the notification `id`, which ends the `wait_for_action` of the reminder thread
+async fn close(id: u32) {
+    let result = async {
+        let bus = zbus::Connection::session().await?;
+        bus.call_method(
+            Some("org.freedesktop.Notifications"),
+            "/org/freedesktop/Notifications",
+            Some("org.freedesktop.Notifications"),
+            "CloseNotification",
+            &(id),
+        )
+        .await?;
+        Ok::<_, zbus::Error>(())
+    }
+    .await;
+    if let Err(err) = result {
+        error!("Unable to close the coffee reminder: {err}");
+    }
+}
+
+/// Time until the next occurrence of `until`, which is tomorrow if it already passed today
+pub fn duration_until(until: NaiveTime, now: NaiveTime) -> Duration {
+    let remaining = (until - now).num_seconds().rem_euclid(24 * 60 * 60);
+    Duration::from_secs(remaining as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn contains(inhibitors: &Inhibitors, reason: &str) -> bool {
+        inhibitors.reasons().iter().any(|x| x.reason == reason)
+    }
+
+    #[test]
+    fn test_reference_counted_reasons() {
+        let mut inhibitors = Inhibitors::default();
+        inhibitors.add("build".to_string(), None, None);
+        inhibitors.add("build".to_string(), Some(42), None);
+        inhibitors.add(MANUAL.to_string(), None, None);
+        inhibitors.add(MANUAL.to_string(), None, None);
+        assert_eq!(
+            inhibitors.reasons(),
+            vec![
+                Reason {
+                    reason: "build".to_string(),
+                    count: 2,
+                    owners: vec![42],
+                },
+                Reason {
+                    reason: MANUAL.to_string(),
+                    count: 1,
+                    owners: vec![],
+                },
+            ]
+        );
+        assert!(inhibitors.remove_reason(MANUAL));
+        assert!(!contains(&inhibitors, MANUAL));
+        assert!(inhibitors.remove_owner(42));
+        assert!(!inhibitors.remove_owner(42));
+        assert!(contains(&inhibitors, "build"));
+        assert!(inhibitors.remove_reason("build"));
+        assert!(!inhibitors.remove_reason("build"));
+        assert!(inhibitors.is_empty());
+    }
+
+    #[test]
+    fn test_reminder_relaxes_only_the_manual_coffee() {
+        let config = CoffeeConfiguration {
+            grace: Some(Duration::from_secs(60)),
+            ..Default::default()
+        };
+        assert_eq!(Reminder::Relax.follow_up(&config), FollowUp::Relax);
+        assert_eq!(Reminder::Ignored.follow_up(&config), FollowUp::Relax);
+        assert_eq!(Reminder::Failed.follow_up(&config), FollowUp::Remind);
+        assert_eq!(
+            Reminder::Ignored.follow_up(&CoffeeConfiguration::default()),
+            FollowUp::Nothing
+        );
+
+        let mut inhibitors = Inhibitors::default();
+        inhibitors.add(MANUAL.to_string(), None, None);
+        inhibitors.add("build".to_string(), None, None);
+        inhibitors.add("cargo".to_string(), Some(42), None);
+        // What `decoffeinate(state, None)` removes after the reminder
+        assert!(inhibitors.remove_reason(MANUAL));
+        assert!(!contains(&inhibitors, MANUAL));
+        assert!(contains(&inhibitors, "build"));
+        assert!(inhibitors.remove_owner(42));
+    }
+
+    #[test]
+    fn test_restore_saved_state() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("glue").join("state.json");
+        let mut inhibitors = Inhibitors::default();
+        inhibitors.add(MANUAL.to_string(), None, None);
+        inhibitors.add("build".to_string(), Some(std::process::id()), None);
+        inhibitors.add("gone".to_string(), Some(u32::MAX), None);
+        inhibitors.add("timed".to_string(), None, Some(Duration::from_secs(60)));
+        inhibitors.save(&path);
+
+        let now = SystemTime::now();
+        let restored = restorable(&path, now);
+        assert_eq!(restored.len(), 3);
+        assert_eq!(restored[0], (MANUAL.to_string(), None, None));
+        assert_eq!(restored[1].0, "build");
+        assert!(restored[2].2.unwrap() <= Duration::from_secs(60));
+        // Expired in the meantime
+        assert_eq!(restorable(&path, now + Duration::from_secs(61)).len(), 2);
+        assert!(restorable(&temp_dir.path().join("missing.json"), now).is_empty());
+    }
+
+    #[test]
+    fn test_relax_keeps_owned_inhibitors() {
+        let mut inhibitors = Inhibitors::default();
+        inhibitors.add(MANUAL.to_string(), None, None);
+        // `coffee drink --owner <pid>` of a script, added after the manual coffee
+        inhibitors.add(MANUAL.to_string(), Some(42), None);
+        assert!(inhibitors.remove_reason(MANUAL));
+        assert!(!inhibitors.remove_reason(MANUAL));
+        assert_eq!(inhibitors.reasons()[0].owners, vec![42]);
+        assert!(inhibitors.remove_owner(42));
+        assert!(inhibitors.is_empty());
+    }
+
+    #[test]
+    fn test_restore_drops_reused_pids() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("state.json");
+        let pid = std::process::id();
+        let started = start_time(pid);
+        assert!(started.is_some());
+        let owned = |reason: &str, started| Inhibitor {
+            id: 0,
+            reason: reason.to_string(),
+            owner: Some(pid),
+            started,
+            until: None,
+            expiry: None,
+        };
+        let save = |boot_id| {
+            let state = SavedState {
+                boot_id,
+                coffee: vec![
+                    owned("same", started),
+                    owned("reused", started.map(|x| x + 1)),
+                ],
+            };
+            std::fs::write(&path, serde_json::to_vec(&state).unwrap()).unwrap();
+        };
+
+        save(boot_id());
+        let restored = restorable(&path, SystemTime::now());
+        assert_eq!(restored, vec![("same".to_string(), Some(pid), None)]);
+        // Pids of another boot belong to other processes
+        save(Some("another boot".to_string()));
+        assert!(restorable(&path, SystemTime::now()).is_empty());
+    }
+
+    #[test]
+    fn test_remove_exited_owners() {
+        let mut inhibitors = Inhibitors::default();
+        inhibitors.add(MANUAL.to_string(), None, None);
+        inhibitors.add("build".to_string(), Some(std::process::id()), None);
+        inhibitors.add("gone".to_string(), Some(u32::MAX), None);
+        assert_eq!(inhibitors.remove_exited(alive), vec![u32::MAX]);
+        assert!(contains(&inhibitors, MANUAL));
+        assert!(contains(&inhibitors, "build"));
+        // The pid was reused by a process started later
+        assert_eq!(
+            inhibitors.remove_exited(|_, started| started.is_none()),
+            vec![std::process::id()]
+        );
+        assert!(!contains(&inhibitors, "build"));
+    }
+
+    #[test]
+    fn test_reconnect_backoff() {
+        assert_eq!(backoff(None), RECONNECT_MIN);
+        assert_eq!(
+            backoff(Some(Duration::from_secs(4))),
+            Duration::from_secs(8)
+        );
+        assert_eq!(backoff(Some(Duration::from_secs(40))), RECONNECT_MAX);
+    }
+
+    #[test]
+    fn test_until_requires_every_inhibitor_to_be_timed() {
+        let mut inhibitors = Inhibitors::default();
+        inhibitors.add("a".to_string(), None, Some(Duration::from_secs(60)));
+        let id = inhibitors.add("b".to_string(), None, Some(Duration::from_secs(120)));
+        let until = inhibitors.until().unwrap();
+        assert!(until > SystemTime::now() + Duration::from_secs(60));
+        inhibitors.add(MANUAL.to_string(), None, None);
+        assert_eq!(inhibitors.until(), None);
+        assert!(inhibitors.expired(id));
+        assert!(!inhibitors.expired(id));
+    }
+
+    #[test]
+    fn test_until_next_occurrence() {
+        let time = |h, m| NaiveTime::from_hms_opt(h, m, 0).unwrap();
+        assert_eq!(
+            duration_until(time(17, 30), time(16, 45)),
+            Duration::from_secs(45 * 60)
+        );
+        assert_eq!(
+            duration_until(time(1, 0), time(23, 0)),
+            Duration::from_secs(2 * 60 * 60)
+        );
+        assert_eq!("17:30".parse::<NaiveTime>().unwrap(), time(17, 30));
     }
-    Ok(())
 }
@@ -1,11 +1,17 @@
+use std::time::Duration;
+
+use chrono::Local;
 use serde::{Deserialize, Serialize};
 
-use crate::cli;
+use crate::{cli, coffee, error::CoffeeError};
 
 #[derive(Serialize, Deserialize)]
 pub enum Command {
     Coffee(Coffee),
     Notification(Notification),
+    Brightness(Brightness),
+    Night(Night),
+    Workspace(Workspace),
 }
 
 #[derive(Serialize, Deserialize)]
@@ -15,12 +21,62 @@ pub enum Notification {
 
 #[derive(Serialize, Deserialize)]
 pub enum Coffee {
-    Drink,
-    Relax,
+    Drink {
+        duration: Option<Duration>,
+        reason: Option<String>,
+        owner: Option<u32>,
+    },
+    Relax {
+        reason: Option<String>,
+    },
+    /// Releases the inhibitors of an exited owner
+    Release {
+        owner: u32,
+    },
+    Toggle,
+    Get,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub enum Brightness {
+    Set {
+        percent: u32,
+        device: Option<String>,
+    },
+    Increase {
+        device: Option<String>,
+    },
+    Decrease {
+        device: Option<String>,
+    },
+}
+
+#[derive(Serialize, Deserialize)]
+pub enum Workspace {
+    /// Windows which demand attention, the daemon tracks them from the Hyprland events
+    Urgent,
+}
+
+#[derive(Serialize, Deserialize)]
+pub enum Night {
+    On,
+    Off,
     Toggle,
+    Auto,
+    Set(u32),
     Get,
 }
 
+impl Brightness {
+    pub fn device(&self) -> Option<&str> {
+        match self {
+            Brightness::Set { device, .. } => device.as_deref(),
+            Brightness::Increase { device } => device.as_deref(),
+            Brightness::Decrease { device } => device.as_deref(),
+        }
+    }
+}
+
 impl From<Coffee> for Command {
     fn from(val: Coffee) -> Self {
         Command::Coffee(val)
@@ -33,13 +89,41 @@ impl From<Notification> for Command {
     }
 }
 
-impl From<cli::CoffeeCommand> for Coffee {
-    fn from(value: cli::CoffeeCommand) -> Self {
-        match value {
-            cli::CoffeeCommand::Drink => Self::Drink,
-            cli::CoffeeCommand::Relax => Self::Relax,
+/// `coffee run` is no daemon command, the client runs the child and sends `Drink`/`Release`
+impl TryFrom<cli::CoffeeCommand> for Coffee {
+    type Error = CoffeeError;
+
+    fn try_from(value: cli::CoffeeCommand) -> Result<Self, Self::Error> {
+        Ok(match value {
+            cli::CoffeeCommand::Drink {
+                duration,
+                until,
+                reason,
+                owner,
+            } => Self::Drink {
+                reason,
+                owner,
+                duration: duration
+                    .map(Into::into)
+                    .or(until.map(|until| coffee::duration_until(until, Local::now().time()))),
+            },
+            cli::CoffeeCommand::Relax { reason } => Self::Relax { reason },
+            cli::CoffeeCommand::Run { .. } => return Err(CoffeeError::ClientOnly("coffee run")),
             cli::CoffeeCommand::Toggle => Self::Toggle,
             cli::CoffeeCommand::Get => Self::Get,
+        })
+    }
+}
+
+impl From<cli::NightCommand> for Night {
+    fn from(value: cli::NightCommand) -> Self {
+        match value {
+            cli::NightCommand::On => Self::On,
+            cli::NightCommand::Off => Self::Off,
+            cli::NightCommand::Toggle => Self::Toggle,
+            cli::NightCommand::Auto => Self::Auto,
+            cli::NightCommand::Set { kelvin } => Self::Set(kelvin),
+            cli::NightCommand::Get => Self::Get,
         }
     }
 }
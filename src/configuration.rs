@@ -1,11 +1,13 @@
 use anyhow::Result;
 use config::Config;
 use log::LevelFilter;
-use std::{path::PathBuf, time::Duration};
+use std::{collections::HashMap, path::PathBuf, time::Duration};
 
 use serde::{Deserialize, Serialize};
 
-use crate::{battery::BatteryStatus, error::ConfigurationError};
+use crate::{
+    battery::BatteryStatus, error::ConfigurationError, rules, utils::glob_match, window::Titles,
+};
 
 /// Glue Configuration Definition
 /// Defining all user accessable file configuration
@@ -13,9 +15,14 @@ use crate::{battery::BatteryStatus, error::ConfigurationError};
 pub struct Configuration {
     pub autostart: Vec<String>,
     pub battery: Battery,
+    pub brightness: Brightness,
     pub coffee: Coffee,
     pub general: General,
     pub hyprland: Hyprland,
+    pub idle: Idle,
+    pub kbd_backlight: KbdBacklight,
+    pub night: Night,
+    pub window: Window,
     pub event: Option<Events>,
 }
 
@@ -27,7 +34,19 @@ impl Configuration {
             .add_source(config::File::with_name(&config_path).required(false))
             .add_source(config::Environment::with_prefix("GLUE"))
             .build()?;
-        Ok(config.try_deserialize::<Configuration>()?)
+        let config = config.try_deserialize::<Configuration>()?;
+        config.validate()?;
+        Ok(config)
+    }
+    fn validate(&self) -> Result<(), ConfigurationError> {
+        if self.coffee.logind.is_empty() {
+            return Err(ConfigurationError::Empty("coffee.logind"));
+        }
+        Titles::new(&self.window)
+            .map_err(|err| ConfigurationError::Pattern("window.rewrite", err.to_string()))?;
+        rules::patterns(&self.coffee.rules.apps)
+            .map_err(|err| ConfigurationError::Pattern("coffee.rules.apps", err.to_string()))?;
+        Ok(())
     }
     fn get_config_path() -> Result<String> {
         let home = std::env::var("HOME")?;
@@ -49,6 +68,7 @@ pub struct Battery {
     pub charging: char,
     pub empty: char,
     pub path: String,
+    pub history: BatteryHistory,
 }
 
 impl Default for Battery {
@@ -59,6 +79,254 @@ impl Default for Battery {
             charging: '󰂄',
             empty: '',
             path: "/sys/class/power_supply/BAT0".to_string(),
+            history: BatteryHistory::default(),
+        }
+    }
+}
+
+/// Samples recorded by the daemon, defaults to `$XDG_STATE_HOME/glue/battery.csv`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BatteryHistory {
+    pub enabled: bool,
+    #[serde(with = "humantime_serde")]
+    pub interval: Duration,
+    pub max_samples: usize,
+    pub path: Option<String>,
+}
+
+impl Default for BatteryHistory {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            interval: Duration::from_secs(5 * 60),
+            max_samples: 30 * 24 * 12,
+            path: None,
+        }
+    }
+}
+
+/// Brightness devices are matched by name or glob, e.g. `intel_backlight` or `ddcci*`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Brightness {
+    /// Devices controlled without `--device`, all devices if empty
+    #[serde(default)]
+    pub devices: Vec<String>,
+    /// Devices never controlled without `--device`
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    /// Step size of `increase`/`decrease` (in %)
+    pub step: u32,
+    /// Step size per device, overriding `step`. The first matching entry wins.
+    #[serde(default)]
+    pub steps: Vec<DeviceStep>,
+    /// Duration of the animated transition done by the daemon, instant if unset
+    #[serde(with = "humantime_serde")]
+    pub transition: Option<Duration>,
+    /// Mapping from the user facing (perceived) percentage to the raw device percentage
+    pub curve: Curve,
+    /// Lowest raw percentage, which is never undercut (in %)
+    pub min: u32,
+    /// Backlight devices watched by the daemon
+    pub path: String,
+    pub auto: AutoBrightness,
+}
+
+/// Brightness following an ambient light sensor, paused by manual changes
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AutoBrightness {
+    pub enabled: bool,
+    /// IIO devices searched for an illuminance sensor
+    pub path: String,
+    /// Sensor used instead of the first one found in `path`
+    pub sensor: Option<String>,
+    /// Mapping from illuminance to brightness, interpolated linearly
+    pub points: Vec<LuxPoint>,
+    /// Weight of a new measurement, between 0 (frozen) and 1 (unsmoothed)
+    pub smoothing: f64,
+    /// Minimal brightness difference (in %), before the brightness is changed
+    pub hysteresis: u32,
+    /// Pause after a manual brightness change
+    #[serde(with = "humantime_serde")]
+    pub pause: Duration,
+}
+
+impl Default for AutoBrightness {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            path: "/sys/bus/iio/devices".to_string(),
+            sensor: None,
+            points: vec![
+                LuxPoint {
+                    lux: 0.0,
+                    brightness: 10,
+                },
+                LuxPoint {
+                    lux: 50.0,
+                    brightness: 35,
+                },
+                LuxPoint {
+                    lux: 500.0,
+                    brightness: 75,
+                },
+                LuxPoint {
+                    lux: 2000.0,
+                    brightness: 100,
+                },
+            ],
+            smoothing: 0.2,
+            hysteresis: 5,
+            pause: Duration::from_secs(5 * 60),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DeviceStep {
+    /// Name or glob of the device
+    pub device: String,
+    /// Step size (in %)
+    pub step: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LuxPoint {
+    pub lux: f64,
+    /// Perceived brightness (in %)
+    pub brightness: u32,
+}
+
+/// Brightness curve, `"linear"`, `"exponential"` or `{ gamma = 2.2 }`
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Curve {
+    Linear,
+    Exponential,
+    Gamma(f64),
+}
+
+impl Default for Brightness {
+    fn default() -> Self {
+        Self {
+            devices: Vec::new(),
+            exclude: Vec::new(),
+            step: 5,
+            steps: Vec::new(),
+            transition: None,
+            curve: Curve::Linear,
+            min: 1,
+            path: "/sys/class/backlight".to_string(),
+            auto: AutoBrightness::default(),
+        }
+    }
+}
+
+impl Brightness {
+    pub fn selects(&self, device: &str) -> bool {
+        let included =
+            self.devices.is_empty() || self.devices.iter().any(|x| glob_match(x, device));
+        included && !self.exclude.iter().any(|x| glob_match(x, device))
+    }
+
+    pub fn step(&self, device: &str) -> u32 {
+        self.steps
+            .iter()
+            .find(|x| glob_match(&x.device, device))
+            .map_or(self.step, |x| x.step)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct KbdBacklight {
+    /// LED devices searched for the keyboard backlight
+    pub path: String,
+    /// Name or glob of the keyboard backlight
+    pub device: String,
+    /// Turn the keyboard backlight off after this period of inactivity
+    #[serde(with = "humantime_serde")]
+    pub idle: Option<Duration>,
+}
+
+impl Default for KbdBacklight {
+    fn default() -> Self {
+        Self {
+            path: "/sys/class/leds".to_string(),
+            device: "*kbd_backlight*".to_string(),
+            idle: None,
+        }
+    }
+}
+
+/// Stages run by the daemon after a period of inactivity, unless coffee is active
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Idle {
+    #[serde(default)]
+    pub stage: Vec<IdleStage>,
+    /// Perceived brightness (in %) of the `dim` action
+    pub dim: u32,
+    /// Locker started by the `lock` action
+    pub lock: String,
+}
+
+impl Default for Idle {
+    fn default() -> Self {
+        Self {
+            stage: Vec::new(),
+            dim: 10,
+            lock: "hyprlock".to_string(),
+        }
+    }
+}
+
+/// `on_idle` is `dim`, `lock`, `dpms`, `suspend` or a shell command, `on_resume` a shell command.
+/// `dim` and `dpms` are undone on resume.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct IdleStage {
+    #[serde(with = "humantime_serde")]
+    pub timeout: Duration,
+    pub on_idle: String,
+    pub on_resume: Option<String>,
+}
+
+/// Color temperature of the outputs, scheduled by the sun when `latitude`/`longitude`
+/// are set or by the fixed `start`/`end` times (`HH:MM`), which take precedence
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Night {
+    /// Follows the schedule from the start of the daemon. Otherwise the gamma of the outputs
+    /// is only taken by `glue night`, other tools keep working until then.
+    pub enabled: bool,
+    /// Temperature in Kelvin during the day
+    pub day: u32,
+    /// Temperature in Kelvin during the night
+    pub night: u32,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    pub start: Option<String>,
+    pub end: Option<String>,
+    /// Time to fade between day and night around sunset and sunrise
+    #[serde(with = "humantime_serde")]
+    pub fade: Duration,
+    /// Duration of the transition after a manual change
+    #[serde(with = "humantime_serde")]
+    pub transition: Duration,
+    pub on: char,
+    pub off: char,
+}
+
+impl Default for Night {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            day: 6500,
+            night: 4000,
+            latitude: None,
+            longitude: None,
+            start: None,
+            end: None,
+            fade: Duration::from_secs(30 * 60),
+            transition: Duration::from_secs(2),
+            on: '󰖔',
+            off: '󰖙',
         }
     }
 }
@@ -67,8 +335,68 @@ impl Default for Battery {
 pub struct Coffee {
     pub coffee: char,
     pub relax: char,
+    /// Asks whether the coffee is still required after this duration
     #[serde(with = "humantime_serde")]
     pub notification: Option<Duration>,
+    /// Relaxes when the reminder is ignored for this duration
+    #[serde(with = "humantime_serde")]
+    pub grace: Option<Duration>,
+    /// Repeats ignored reminders instead of asking once
+    pub repeat: bool,
+    /// Inhibits idle through the Wayland compositor, systemd-logind or both
+    pub backend: InhibitBackend,
+    /// What-values of the logind inhibitor lock: `idle`, `sleep`, `handle-lid-switch`, ...
+    #[serde(default)]
+    pub logind: Vec<String>,
+    /// Restores the coffee after a restart of the daemon
+    pub restore: bool,
+    pub rules: CoffeeRules,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum InhibitBackend {
+    Wayland,
+    Logind,
+    Both,
+}
+
+impl InhibitBackend {
+    pub fn wayland(self) -> bool {
+        matches!(self, InhibitBackend::Wayland | InhibitBackend::Both)
+    }
+
+    pub fn logind(self) -> bool {
+        matches!(self, InhibitBackend::Logind | InhibitBackend::Both)
+    }
+}
+
+/// Automatic coffee while any enabled rule matches, independent of the manual coffee
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CoffeeRules {
+    /// The focused window is fullscreen
+    pub fullscreen: bool,
+    /// An MPRIS player is playing, queried with `playerctl`
+    pub media: bool,
+    /// An application records from the microphone, queried with `pactl`
+    pub mic: bool,
+    /// Regular expressions matched against the class and title of all windows
+    #[serde(default)]
+    pub apps: Vec<String>,
+    #[serde(with = "humantime_serde")]
+    pub interval: Duration,
+}
+
+impl Default for CoffeeRules {
+    fn default() -> Self {
+        Self {
+            fullscreen: false,
+            media: false,
+            mic: false,
+            apps: Vec::new(),
+            interval: Duration::from_secs(5),
+        }
+    }
 }
 
 impl Default for Coffee {
@@ -77,6 +405,12 @@ impl Default for Coffee {
             coffee: '',
             relax: '󰒲',
             notification: None,
+            grace: None,
+            repeat: false,
+            backend: InhibitBackend::Wayland,
+            logind: vec!["idle".to_string(), "sleep".to_string()],
+            restore: true,
+            rules: CoffeeRules::default(),
         }
     }
 }
@@ -99,14 +433,83 @@ impl Default for General {
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Hyprland {
     pub default_spaces: usize,
+    /// Output of the `workspace` eww variable
+    pub format: WorkspaceFormat,
+    /// Yuck of one workspace button, see docs/config.md for the placeholders
+    pub template: String,
+    pub state_icons: WorkspaceStateIcons,
+    /// Icons of workspaces by name or id, replacing the icon of the state
+    #[serde(default)]
+    pub workspace_icons: HashMap<String, String>,
+    /// Icons of window classes, replacing the icon of the desktop entry
+    #[serde(default)]
+    pub window_icons: HashMap<String, String>,
+    /// Notify when a window demands attention
+    pub urgent_notification: bool,
 }
 
 impl Default for Hyprland {
     fn default() -> Self {
-        Self { default_spaces: 5 }
+        Self {
+            default_spaces: 5,
+            format: WorkspaceFormat::Yuck,
+            template: "(button :onclick \"hyprctl dispatch {dispatch}\" :class \"workspace\"  (box :class \"workspace-{state}\" :space-evenly \"false\" \"{icon}\"))".to_string(),
+            state_icons: WorkspaceStateIcons::default(),
+            workspace_icons: HashMap::new(),
+            window_icons: HashMap::new(),
+            urgent_notification: false,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum WorkspaceFormat {
+    /// Rendered `hyprland.template` buttons
+    Yuck,
+    /// List of workspace objects
+    Json,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct WorkspaceStateIcons {
+    pub empty: char,
+    pub active: char,
+    pub contains: char,
+    pub special: char,
+    pub urgent: char,
+}
+
+impl Default for WorkspaceStateIcons {
+    fn default() -> Self {
+        Self {
+            empty: '',
+            active: '',
+            contains: '',
+            special: '',
+            urgent: '',
+        }
     }
 }
 
+/// Title of the `active_window` eww variable
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Window {
+    /// Titles are shortened to this number of characters
+    pub max_length: Option<usize>,
+    /// Only the first matching rule is applied
+    #[serde(default)]
+    pub rewrite: Vec<TitleRewrite>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TitleRewrite {
+    /// Regex matched against the title
+    pub pattern: String,
+    /// Replacement of the match, `$1` refers to the first capture group
+    pub replace: String,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct Events {
     pub(crate) battery: Vec<BatteryEvent>,
@@ -120,3 +523,73 @@ pub struct BatteryEvent {
     pub(crate) shell: Option<String>,
     pub(crate) hooks: Option<Vec<String>>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_rejects_empty_logind() {
+        let mut config = Configuration::default();
+        assert!(config.validate().is_ok());
+        config.coffee.logind.clear();
+        assert!(matches!(
+            config.validate(),
+            Err(ConfigurationError::Empty("coffee.logind"))
+        ));
+    }
+
+    #[test]
+    fn test_validate_rejects_invalid_rewrite() {
+        let mut config = Configuration::default();
+        config.window.rewrite.push(TitleRewrite {
+            pattern: "(unclosed".to_string(),
+            replace: String::new(),
+        });
+        assert!(matches!(
+            config.validate(),
+            Err(ConfigurationError::Pattern("window.rewrite", _))
+        ));
+        let mut config = Configuration::default();
+        config.coffee.rules.apps.push("[zoom".to_string());
+        assert!(matches!(
+            config.validate(),
+            Err(ConfigurationError::Pattern("coffee.rules.apps", _))
+        ));
+    }
+
+    #[test]
+    fn test_brightness_selects() {
+        let config = Brightness {
+            devices: vec!["intel_*".to_string(), "ddcci*".to_string()],
+            exclude: vec!["ddcci2".to_string()],
+            ..Default::default()
+        };
+        assert!(config.selects("intel_backlight"));
+        assert!(config.selects("ddcci1"));
+        assert!(!config.selects("ddcci2"));
+        assert!(!config.selects("acpi_video0"));
+        assert!(Brightness::default().selects("acpi_video0"));
+    }
+
+    #[test]
+    fn test_brightness_step_first_match() {
+        let config = Brightness {
+            step: 5,
+            steps: vec![
+                DeviceStep {
+                    device: "intel_backlight".to_string(),
+                    step: 2,
+                },
+                DeviceStep {
+                    device: "*".to_string(),
+                    step: 10,
+                },
+            ],
+            ..Default::default()
+        };
+        assert_eq!(config.step("intel_backlight"), 2);
+        assert_eq!(config.step("ddcci1"), 10);
+        assert_eq!(Brightness::default().step("ddcci1"), 5);
+    }
+}
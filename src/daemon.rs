@@ -10,18 +10,25 @@ use log::{error, info};
 use notify_rust::Notification;
 use rand::distr::Alphanumeric;
 use rand::rng;
-use tokio::sync::Mutex;
+use tokio::sync::{Mutex, Notify};
 use tokio::time::interval;
 
+use crate::ambient::AmbientLight;
 use crate::autostart::auto_start;
-use crate::coffee::{coffeinate, decoffeinate, CoffeeResponse};
+use crate::coffee::{
+    self, coffeinate, decoffeinate, release_owner, CoffeeResponse, Owners, Reconnect,
+};
 use crate::commands::{self, Command};
 use crate::configuration::Configuration;
 use crate::error::{DaemonClientError, DaemonError};
 use crate::eww::{self, eww_update};
 use crate::monitor::Monitor;
-use crate::monitor::{monitor, Battery};
-use crate::{hyprland, DaemonState, GLUE_PATH};
+use crate::monitor::{monitor, watch, Backlight, Battery};
+use crate::rules::CoffeeRules;
+use crate::wayland::idle_notifications;
+use crate::{
+    brightness, hyprland, idle, kbd_backlight, keyboard, DaemonState, IdleState, GLUE_PATH,
+};
 
 pub fn client(command: Command) -> Result<Vec<u8>, DaemonClientError> {
     let mut client = Client::new(GLUE_PATH).map_err(DaemonClientError::IPCError)?;
@@ -47,16 +54,62 @@ pub async fn daemon(
     }
 
     let state = DaemonState::new(config.clone())?;
+    let kbd_backlight_idle = config.kbd_backlight.idle.map(|timeout| {
+        idle_notifications(
+            state.wayland_connection.subscribe(),
+            vec![timeout],
+            "Keyboard backlight idle",
+        )
+    });
+    let night = state.night.clone();
+    let urgent = state.urgent.clone();
+    let connections = state.wayland_connection.subscribe();
+    let idle_timeouts = config
+        .idle
+        .stage
+        .iter()
+        .map(|stage| stage.timeout)
+        .collect::<Vec<_>>();
+    let idle_events = match idle_timeouts.is_empty() {
+        true => None,
+        false => Some(idle_notifications(
+            state.wayland_connection.subscribe(),
+            idle_timeouts,
+            "Idle stages",
+        )),
+    };
+    let state = Arc::new(Mutex::new(state));
+    coffee::restore(&state, &config).await;
+    if let Err(err) = keyboard::eww_keyboard_update() {
+        error!("{err}");
+    }
 
     tokio::try_join!(
         async {
-            hyprland::listener(config.clone())
+            hyprland::listener(config.clone(), urgent)
+                .map_err(|err| DaemonError::Listener(err.to_string()))?
                 .start_listener_async()
                 .await
                 .map_err(|err| DaemonError::Listener(err.to_string()))
         },
-        server(GLUE_PATH, state, config.clone()),
-        monitor_daemon(config.clone())
+        server(GLUE_PATH, state.clone(), config.clone()),
+        monitor_daemon(config.clone(), state.clone()),
+        async {
+            if let Some(events) = kbd_backlight_idle {
+                kbd_backlight::idle(config.clone(), events).await;
+            }
+            Ok(())
+        },
+        async {
+            night.run(connections).await;
+            Ok(())
+        },
+        async {
+            if let Some(events) = idle_events {
+                idle::idle(config.clone(), state.clone(), events).await;
+            }
+            Ok(())
+        }
     )?;
     Ok(())
 }
@@ -90,22 +143,51 @@ fn setup_logging(config: &Arc<Configuration>, daemon_id: &str) -> Result<(), Dae
     Ok(())
 }
 
-async fn monitor_daemon(config: Arc<Configuration>) -> Result<(), DaemonError> {
+async fn monitor_daemon(
+    config: Arc<Configuration>,
+    state: Arc<Mutex<DaemonState>>,
+) -> Result<(), DaemonError> {
     let mut ticker = interval(Duration::from_secs(1));
-    let battery = Battery::try_new(config).await.unwrap();
-    let mut services: Vec<Box<dyn Monitor>> = vec![Box::new(battery)];
+    let battery = Battery::try_new(config.clone()).await.unwrap();
+    let backlight = Backlight::new(config.clone()).await;
+    let mut services: Vec<Box<dyn Monitor>> = vec![Box::new(battery), Box::new(backlight)];
+    let (manual_brightness, transitions) = {
+        let state = state.lock().await;
+        (
+            state.manual_brightness.clone(),
+            state.brightness_transition.clone(),
+        )
+    };
+    if let Some(ambient) = AmbientLight::try_new(config.clone(), manual_brightness, transitions) {
+        services.push(Box::new(ambient));
+    }
+    services.push(Box::new(Owners::new(config.clone(), state.clone())));
+    services.push(Box::new(Reconnect::new(config.clone(), state.clone())));
+    if let Some(rules) = CoffeeRules::try_new(config, state) {
+        services.push(Box::new(rules));
+    }
+    let wake = Arc::new(Notify::new());
+    watch(
+        services
+            .iter()
+            .flat_map(|service| service.watch())
+            .collect(),
+        wake.clone(),
+    );
     loop {
         monitor(&mut services).await?;
-        ticker.tick().await;
+        tokio::select! {
+            _ = ticker.tick() => {}
+            _ = wake.notified() => {}
+        }
     }
 }
 
 async fn server(
     socket: &str,
-    state: DaemonState,
+    state: Arc<Mutex<DaemonState>>,
     config: Arc<Configuration>,
 ) -> Result<(), DaemonError> {
-    let state = Arc::new(Mutex::new(state));
     let server = glue_ipc::tokio::server::Server::new(socket)
         .await
         .map_err(DaemonError::SocketError)?;
@@ -114,39 +196,8 @@ async fn server(
             |command, state: Arc<Mutex<DaemonState>>, mut stream| {
                 let config = config.clone();
                 async move {
-                    match command {
-                        Command::Coffee(coffee) => match coffee {
-                            commands::Coffee::Drink => {
-                                info!("Drink Coffee");
-                                let result = coffeinate(state.lock().await.deref_mut());
-                                if let Err(err) = result {
-                                    error!("{err}");
-                                }
-                            }
-                            commands::Coffee::Relax => {
-                                info!("I'm getting sleepy!");
-                                let result = decoffeinate(state.lock().await.deref_mut());
-                                if let Err(err) = result {
-                                    error!("{err}");
-                                }
-                            }
-                            commands::Coffee::Toggle => {
-                                info!("Toggle Coffee State");
-                                let result = {
-                                    let mut state = state.lock().await;
-                                    match &state.idle_inhibited {
-                                        true => decoffeinate(&mut state),
-                                        false => coffeinate(&mut state),
-                                    }
-                                };
-                                if let Err(err) = result {
-                                    error!("{err}");
-                                }
-                            }
-                            commands::Coffee::Get => {
-                                info!("Coffee Get Request");
-                            }
-                        },
+                    let response = match command {
+                        Command::Coffee(coffee) => coffee_handler(coffee, &state, &config).await,
                         Command::Notification(notification) => match notification {
                             commands::Notification::Test(text) => {
                                 info!("Notification Test");
@@ -155,27 +206,40 @@ async fn server(
                                 if let Err(error) = result {
                                     error!("Unable to send notification: {error:#?}");
                                 }
+                                Vec::new()
                             }
                         },
-                    };
-
-                    match state.lock().await.wayland_idle.get() {
-                        Ok(state) => {
-                            let try_state_buffer = serde_json::to_vec(&state);
-                            let Ok(state_buffer) = try_state_buffer else {
-                                error!("Coffee Get: {try_state_buffer:#?}");
-                                return;
-                            };
-                            let mut client = Protocol::new(&mut stream);
-                            client.write_message(&state_buffer).await.unwrap();
-                            if let Err(err) = eww_update(eww::EwwVariable::Coffee(
-                                CoffeeResponse::new(&config, &state.into()),
-                            )) {
-                                error!("Unable to update EWW: {err:#?}");
+                        Command::Brightness(request) => {
+                            info!("Brightness Request");
+                            let transitions = {
+                                let state = state.lock().await;
+                                state.manual_brightness.touch();
+                                state.brightness_transition.clone()
                             };
+                            let result =
+                                brightness::transition(request, config.clone(), &transitions)
+                                    .await
+                                    .map_err(|err| {
+                                        error!("{err}");
+                                        err.to_string()
+                                    });
+                            serde_json::to_vec(&result).unwrap_or_default()
+                        }
+                        Command::Night(request) => {
+                            info!("Night Light Request");
+                            let night = state.lock().await.night.clone();
+                            serde_json::to_vec(&night.command(request)).unwrap_or_default()
+                        }
+                        Command::Workspace(commands::Workspace::Urgent) => {
+                            let urgent = state.lock().await.urgent.clone();
+                            let urgent = urgent.lock().unwrap().clone();
+                            serde_json::to_vec(&urgent).unwrap_or_default()
                         }
-                        Err(_err) => todo!(),
                     };
+                    let mut client = Protocol::new(&mut stream);
+                    if let Err(err) = client.write_message(&response).await {
+                        error!("Unable to respond: {err}");
+                    }
                 }
             },
             state,
@@ -183,3 +247,66 @@ async fn server(
         .await;
     Ok(())
 }
+
+async fn coffee_handler(
+    coffee: commands::Coffee,
+    state: &Arc<Mutex<DaemonState>>,
+    config: &Arc<Configuration>,
+) -> Vec<u8> {
+    match coffee {
+        commands::Coffee::Drink {
+            duration,
+            reason,
+            owner,
+        } => {
+            info!("Drink Coffee");
+            let mut guard = state.lock().await;
+            let result = coffeinate(state, &mut guard, config, reason, owner, duration).await;
+            if let Err(err) = result {
+                error!("{err}");
+            }
+        }
+        commands::Coffee::Relax { reason } => {
+            info!("I'm getting sleepy!");
+            let result = decoffeinate(state.lock().await.deref_mut(), reason.as_deref()).await;
+            if let Err(err) = result {
+                error!("{err}");
+            }
+        }
+        commands::Coffee::Release { owner } => {
+            info!("Release Coffee of {owner}");
+            let result = release_owner(state.lock().await.deref_mut(), owner).await;
+            if let Err(err) = result {
+                error!("{err}");
+            }
+        }
+        commands::Coffee::Toggle => {
+            info!("Toggle Coffee State");
+            let result = {
+                let mut guard = state.lock().await;
+                match guard.inhibitors.manual() {
+                    true => decoffeinate(&mut guard, None).await,
+                    false => coffeinate(state, &mut guard, config, None, None, None).await,
+                }
+            };
+            if let Err(err) = result {
+                error!("{err}");
+            }
+        }
+        commands::Coffee::Get => {
+            info!("Coffee Get Request");
+        }
+    };
+
+    let state = state.lock().await;
+    let idle = IdleState::from(&*state);
+    let try_state_buffer = serde_json::to_vec(&idle);
+    let Ok(state_buffer) = try_state_buffer else {
+        error!("Coffee Get: {try_state_buffer:#?}");
+        return Vec::new();
+    };
+    if let Err(err) = eww_update(eww::EwwVariable::Coffee(CoffeeResponse::new(config, &idle))) {
+        error!("Unable to update EWW: {err:#?}");
+    };
+    state_buffer
+}
@@ -0,0 +1,203 @@
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, PoisonError};
+use std::time::SystemTime;
+
+/// Fields of a desktop entry, which map a window class to its icon
+#[derive(Debug, Clone, PartialEq)]
+struct DesktopEntry {
+    /// File name without `.desktop`, e.g. `org.mozilla.firefox`
+    id: String,
+    wm_class: Option<String>,
+    icon: String,
+}
+
+/// Desktop entries with the modification times of their directories when they were read
+struct Entries {
+    dirs: Vec<PathBuf>,
+    modified: Vec<Option<SystemTime>>,
+    list: Vec<DesktopEntry>,
+}
+
+impl Entries {
+    fn load(dirs: Vec<PathBuf>) -> Self {
+        Self {
+            modified: modified(&dirs),
+            list: load(&dirs),
+            dirs,
+        }
+    }
+
+    /// Icon of the window `class`, the entries are read again on a miss if a directory
+    /// changed, e.g. after an application was installed
+    fn icon(&mut self, class: &str) -> Option<String> {
+        if find(&self.list, class).is_none() && modified(&self.dirs) != self.modified {
+            *self = Self::load(std::mem::take(&mut self.dirs));
+        }
+        find(&self.list, class).map(str::to_string)
+    }
+}
+
+fn modified(dirs: &[PathBuf]) -> Vec<Option<SystemTime>> {
+    dirs.iter()
+        .map(|dir| std::fs::metadata(dir).and_then(|x| x.modified()).ok())
+        .collect()
+}
+
+static ENTRIES: Mutex<Option<Entries>> = Mutex::new(None);
+
+/// Icon name of the application with the window `class`, the lowercase class if no desktop
+/// entry matches
+pub fn icon(class: &str) -> String {
+    let mut entries = ENTRIES.lock().unwrap_or_else(PoisonError::into_inner);
+    entries
+        .get_or_insert_with(|| Entries::load(application_dirs()))
+        .icon(class)
+        .unwrap_or_else(|| class.to_lowercase())
+}
+
+/// `applications` of `$XDG_DATA_HOME` and `$XDG_DATA_DIRS`, in the order of precedence
+fn application_dirs() -> Vec<PathBuf> {
+    let data_home = match std::env::var("XDG_DATA_HOME") {
+        Ok(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var("HOME").unwrap_or_default())
+            .join(".local")
+            .join("share"),
+    };
+    let data_dirs = match std::env::var("XDG_DATA_DIRS") {
+        Ok(dirs) if !dirs.is_empty() => dirs,
+        _ => "/usr/local/share:/usr/share".to_string(),
+    };
+    std::iter::once(data_home)
+        .chain(data_dirs.split(':').map(PathBuf::from))
+        .map(|dir| dir.join("applications"))
+        .collect()
+}
+
+/// Entries of all directories, an entry shadows entries with the same id in later directories
+fn load(dirs: &[PathBuf]) -> Vec<DesktopEntry> {
+    let mut entries: Vec<DesktopEntry> = Vec::new();
+    for dir in dirs {
+        let Ok(files) = std::fs::read_dir(dir) else {
+            continue;
+        };
+        for file in files.flatten() {
+            let path = file.path();
+            let Some(entry) = read(&path) else {
+                continue;
+            };
+            if !entries.iter().any(|x| x.id == entry.id) {
+                entries.push(entry);
+            }
+        }
+    }
+    entries
+}
+
+fn read(path: &Path) -> Option<DesktopEntry> {
+    if path.extension()? != "desktop" {
+        return None;
+    }
+    let id = path.file_stem()?.to_str()?;
+    parse(id, &std::fs::read_to_string(path).ok()?)
+}
+
+/// Parses the `[Desktop Entry]` group, entries without an icon are ignored
+fn parse(id: &str, content: &str) -> Option<DesktopEntry> {
+    let mut in_entry = false;
+    let mut wm_class = None;
+    let mut icon = None;
+    for line in content.lines().map(str::trim) {
+        if line.starts_with('[') {
+            in_entry = line == "[Desktop Entry]";
+            continue;
+        }
+        if !in_entry {
+            continue;
+        }
+        match line
+            .split_once('=')
+            .map(|(key, value)| (key.trim(), value.trim()))
+        {
+            Some(("StartupWMClass", value)) => wm_class = Some(value.to_string()),
+            Some(("Icon", value)) if !value.is_empty() => icon = Some(value.to_string()),
+            _ => {}
+        }
+    }
+    Some(DesktopEntry {
+        id: id.to_string(),
+        wm_class,
+        icon: icon?,
+    })
+}
+
+/// Matches `StartupWMClass`, the id or the last component of a reverse DNS id,
+/// e.g. `firefox` for `org.mozilla.firefox`
+fn find<'a>(entries: &'a [DesktopEntry], class: &str) -> Option<&'a str> {
+    let by = |matches: &dyn Fn(&DesktopEntry) -> bool| {
+        entries.iter().find(|x| matches(x)).map(|x| x.icon.as_str())
+    };
+    by(&|x| {
+        x.wm_class
+            .as_deref()
+            .is_some_and(|wm_class| wm_class.eq_ignore_ascii_case(class))
+    })
+    .or_else(|| by(&|x| x.id.eq_ignore_ascii_case(class)))
+    .or_else(|| {
+        by(&|x| {
+            x.id.rsplit('.')
+                .next()
+                .is_some_and(|name| name.eq_ignore_ascii_case(class))
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_icon_of_class() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let (user, system) = (temp_dir.path().join("user"), temp_dir.path().join("system"));
+        std::fs::create_dir_all(&user).unwrap();
+        std::fs::create_dir_all(&system).unwrap();
+        std::fs::write(
+            system.join("code.desktop"),
+            "[Desktop Entry]\nName=Code\nIcon=vscode\nStartupWMClass=Code\n\n[Desktop Action new]\nIcon=other\n",
+        )
+        .unwrap();
+        std::fs::write(
+            system.join("org.mozilla.firefox.desktop"),
+            "[Desktop Entry]\nIcon=firefox\n",
+        )
+        .unwrap();
+        std::fs::write(
+            system.join("kitty.desktop"),
+            "[Desktop Entry]\nIcon=kitty\n",
+        )
+        .unwrap();
+        std::fs::write(
+            user.join("kitty.desktop"),
+            "[Desktop Entry]\nIcon=my-kitty\n",
+        )
+        .unwrap();
+
+        let entries = load(&[user, system]);
+        assert_eq!(find(&entries, "Code"), Some("vscode"));
+        assert_eq!(find(&entries, "firefox"), Some("firefox"));
+        assert_eq!(find(&entries, "kitty"), Some("my-kitty"));
+        assert_eq!(find(&entries, "unknown"), None);
+    }
+
+    #[test]
+    fn test_reload_entries_on_miss() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let dir = temp_dir.path().join("applications");
+        let mut entries = Entries::load(vec![dir.clone()]);
+        assert_eq!(entries.icon("kitty"), None);
+        // Installed after the entries were read
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("kitty.desktop"), "[Desktop Entry]\nIcon=kitty\n").unwrap();
+        assert_eq!(entries.icon("kitty"), Some("kitty".to_string()));
+    }
+}
@@ -31,6 +31,14 @@ pub enum GlueError {
     Brightness(BrightnessError),
     #[error("{}", .0)]
     DaemonClient(DaemonClientError),
+    #[error("{}", .0)]
+    KbdBacklight(KbdBacklightError),
+    #[error("{}", .0)]
+    Idle(IdleError),
+    #[error("{}", .0)]
+    Window(WindowError),
+    #[error("{}", .0)]
+    Keyboard(KeyboardError),
 }
 
 #[derive(Error, Debug)]
@@ -39,6 +47,40 @@ pub enum BrightnessError {
     Brightness(brightness::Error),
     #[error("{}", .0)]
     Serialization(String),
+    #[error("No brightness device matches {}", .0)]
+    NoDevice(String),
+    #[error("Brightness task failed: {}", .0)]
+    Task(ErrorMessage),
+    #[error("The daemon was unable to change the brightness: {}", .0)]
+    Daemon(ErrorMessage),
+}
+
+#[derive(Error, Debug)]
+pub enum KbdBacklightError {
+    #[error("No keyboard backlight found in {}", .0)]
+    NotFound(String),
+    #[error("Unable to read file: {}\nOS: {}", .0, .1)]
+    ReadFile(String, String),
+    #[error("Unable to write file: {}\nOS: {}", .0, .1)]
+    WriteFile(String, String),
+    #[error("Unable to parse the keyboard backlight level: {}", .0)]
+    Parse(String),
+    #[error("Unable to set the keyboard backlight via logind: {}", .0)]
+    Logind(String),
+    #[error("Keyboard backlight task failed: {}", .0)]
+    Task(ErrorMessage),
+}
+
+#[derive(Error, Debug)]
+pub enum IdleError {
+    #[error("Unable to run the idle command {}: {}", .0, .1)]
+    Shell(Command, ErrorMessage),
+    #[error("Unable to change DPMS: {}", .0)]
+    Dpms(ErrorMessage),
+    #[error("Unable to suspend via logind: {}", .0)]
+    Logind(ErrorMessage),
+    #[error("Idle action failed: {}", .0)]
+    Task(ErrorMessage),
 }
 
 #[derive(Error, Debug)]
@@ -61,6 +103,18 @@ pub enum BatteryError {
     ReadFile(String, String),
     #[error("Unable to parse {} as u8 representing the battery level (in %)", .0)]
     ParseCapacity(String),
+    #[error("Unable to write file: {}\nOS: {}", .0, .1)]
+    WriteFile(String, String),
+    #[error("Unable to parse {} as u8 representing a charge threshold (in %)", .0)]
+    ParseThreshold(String),
+    #[error("Unable to parse {} as battery capacity (in µWh/µAh)", .0)]
+    ParseHealth(String),
+    #[error("Invalid charge thresholds: start {} has to be below end {} (max. 100)", .0, .1)]
+    InvalidThreshold(u8, u8),
+    #[error("{} is not supported by this battery", .0)]
+    Unsupported(&'static str),
+    #[error("No AC adapter found next to {}", .0)]
+    NoAcAdapter(String),
 }
 
 #[derive(Debug, Error)]
@@ -105,6 +159,30 @@ pub enum WorkspaceError {
     Hyprland(HyprError),
     #[error("Unable to update Workspace, recieved: {}", .0)]
     Command(CommandError),
+    #[error("Unknown monitor {}", .0)]
+    UnknownMonitor(String),
+}
+
+#[derive(Debug, Error)]
+pub enum WindowError {
+    #[error("Unable to get the active window from Hyprland\nERROR: {}", .0)]
+    Hyprland(HyprError),
+    #[error("Unable to update the active window, recieved: {}", .0)]
+    Command(CommandError),
+    #[error("Invalid window rewrite: {}", .0)]
+    Rewrite(ErrorMessage),
+}
+
+#[derive(Debug, Error)]
+pub enum KeyboardError {
+    #[error("Unable to reach Hyprland\nERROR: {}", .0)]
+    Hyprland(HyprError),
+    #[error("No main keyboard found")]
+    NotFound,
+    #[error("The main keyboard has no layout {}", .0)]
+    UnknownLayout(String),
+    #[error("Unable to update the keyboard layout, recieved: {}", .0)]
+    Command(CommandError),
 }
 
 #[allow(unused)]
@@ -112,6 +190,10 @@ pub enum WorkspaceError {
 pub enum ConfigurationError {
     #[error("Invalid Path found: {:?}", .0)]
     InvalidPath(PathBuf),
+    #[error("{} must not be empty", .0)]
+    Empty(&'static str),
+    #[error("Invalid pattern in {}: {}", .0, .1)]
+    Pattern(&'static str, ErrorMessage),
 }
 
 #[derive(Debug, Error)]
@@ -120,6 +202,12 @@ pub enum CoffeeError {
     IPCError(DaemonClientError),
     #[error("Something with Wayland didn't work: {}", .0)]
     WaylandError(WaylandClientError),
+    #[error("Unable to run {}: {}", .0, .1)]
+    Run(Command, ErrorMessage),
+    #[error("Unable to take the logind inhibitor lock: {}", .0)]
+    Logind(ErrorMessage),
+    #[error("{} is handled by the client, not the daemon", .0)]
+    ClientOnly(&'static str),
 }
 
 #[derive(Debug, Error)]
@@ -4,17 +4,29 @@ use crate::audio::AudioSettings;
 use crate::brightness::BrightnessSettings;
 use crate::coffee::CoffeeResponse;
 use crate::error::CommandError;
+use crate::hyprland::Submap;
+use crate::kbd_backlight::KbdBacklightState;
+use crate::keyboard::KeyboardState;
 use crate::mic::MicSettings;
 use crate::monitor::BatteryState;
+use crate::night::NightState;
+use crate::window::ActiveWindow;
 
 #[allow(dead_code)]
 pub(crate) enum EwwVariable {
     Workspace(String),
+    /// Workspaces of one monitor
+    MonitorWorkspace(String, String),
     Audio(AudioSettings),
     Mic(MicSettings),
     Coffee(CoffeeResponse),
     Brightness(BrightnessSettings),
     Battery(BatteryState),
+    KbdBacklight(KbdBacklightState),
+    Night(NightState),
+    ActiveWindow(ActiveWindow),
+    Keyboard(KeyboardState),
+    Submap(Submap),
 }
 
 #[cfg(not(test))]
@@ -23,6 +35,9 @@ pub fn eww_update(variable: EwwVariable) -> Result<(), CommandError> {
     command.arg("update");
     let argument = match variable {
         EwwVariable::Workspace(id) => format!("workspace={id}"),
+        EwwVariable::MonitorWorkspace(monitor, workspaces) => {
+            format!("workspace_{monitor}={workspaces}")
+        }
         EwwVariable::Audio(settings) => {
             format!("audio={}", serde_json::to_string(&settings).unwrap())
         }
@@ -37,6 +52,21 @@ pub fn eww_update(variable: EwwVariable) -> Result<(), CommandError> {
         EwwVariable::Battery(status) => {
             format!("battery={}", serde_json::to_string(&status).unwrap())
         }
+        EwwVariable::KbdBacklight(state) => {
+            format!("kbd_backlight={}", serde_json::to_string(&state).unwrap())
+        }
+        EwwVariable::Night(state) => {
+            format!("night={}", serde_json::to_string(&state).unwrap())
+        }
+        EwwVariable::ActiveWindow(window) => {
+            format!("active_window={}", serde_json::to_string(&window).unwrap())
+        }
+        EwwVariable::Keyboard(state) => {
+            format!("keyboard={}", serde_json::to_string(&state).unwrap())
+        }
+        EwwVariable::Submap(submap) => {
+            format!("submap={}", serde_json::to_string(&submap).unwrap())
+        }
     };
     command.arg(&argument);
     command
@@ -0,0 +1,230 @@
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+
+use crate::battery::BatteryStatus;
+use crate::configuration::Configuration;
+use crate::error::BatteryError;
+use crate::utils::state_dir;
+
+/// A single battery measurement, stored as one CSV line: `timestamp,capacity,status,power`
+#[derive(Serialize, Debug, PartialEq)]
+pub(crate) struct Sample {
+    /// Seconds since the UNIX epoch
+    timestamp: u64,
+    capacity: u8,
+    status: BatteryStatus,
+    /// Power draw in W, if reported by the battery
+    power: Option<f32>,
+}
+
+impl Sample {
+    pub(crate) fn now(capacity: u8, status: BatteryStatus, power: Option<f32>) -> Self {
+        Self {
+            timestamp: unix_time(SystemTime::now()),
+            capacity,
+            status,
+            power,
+        }
+    }
+
+    fn to_line(&self) -> String {
+        format!(
+            "{},{},{},{}",
+            self.timestamp,
+            self.capacity,
+            self.status,
+            self.power.map(|x| format!("{x:.2}")).unwrap_or_default()
+        )
+    }
+
+    fn from_line(line: &str) -> Option<Self> {
+        let mut fields = line.split(',');
+        let timestamp = fields.next()?.parse().ok()?;
+        let capacity = fields.next()?.parse().ok()?;
+        let status = fields.next()?.try_into().ok()?;
+        let power = fields.next().and_then(|x| x.parse().ok());
+        Some(Self {
+            timestamp,
+            capacity,
+            status,
+            power,
+        })
+    }
+}
+
+/// Bounded on-disk battery history, keeping at most `max_samples` entries
+pub(crate) struct History {
+    path: PathBuf,
+    max_samples: usize,
+    /// Lines of the file, read on the first append and counted afterwards
+    lines: Mutex<Option<usize>>,
+}
+
+impl History {
+    pub(crate) fn new(config: &Configuration) -> Self {
+        let path = match &config.battery.history.path {
+            Some(path) => PathBuf::from(path),
+            None => state_dir().join("battery.csv"),
+        };
+        Self {
+            path,
+            max_samples: config.battery.history.max_samples,
+            lines: Mutex::new(None),
+        }
+    }
+
+    pub(crate) fn append(&self, sample: &Sample) -> Result<(), BatteryError> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent).map_err(|x| self.write_error(x))?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .map_err(|x| self.write_error(x))?;
+        writeln!(file, "{}", sample.to_line()).map_err(|x| self.write_error(x))?;
+        let mut lines = self.lines.lock().unwrap();
+        let count = match *lines {
+            Some(count) => count + 1,
+            None => self.read_lines()?.len(),
+        };
+        *lines = Some(match count > self.max_samples + self.max_samples / 10 {
+            true => self.compact()?,
+            false => count,
+        });
+        Ok(())
+    }
+
+    /// Returns all samples recorded within the last `duration`
+    pub(crate) fn since(&self, duration: Duration) -> Result<Vec<Sample>, BatteryError> {
+        let start = unix_time(
+            SystemTime::now()
+                .checked_sub(duration)
+                .unwrap_or(UNIX_EPOCH),
+        );
+        Ok(self
+            .read_lines()?
+            .iter()
+            .filter_map(|line| Sample::from_line(line))
+            .filter(|sample| sample.timestamp >= start)
+            .collect())
+    }
+
+    /// Drops the oldest samples, returning the number of lines kept. `append` calls it
+    /// once the file grew 10% beyond its bound, so only then the file is read again.
+    fn compact(&self) -> Result<usize, BatteryError> {
+        let lines = self.read_lines()?;
+        let kept = &lines[lines.len().saturating_sub(self.max_samples)..];
+        let mut content = kept.join("\n");
+        content.push('\n');
+        fs::write(&self.path, content).map_err(|x| self.write_error(x))?;
+        Ok(kept.len())
+    }
+
+    fn read_lines(&self) -> Result<Vec<String>, BatteryError> {
+        if !Path::new(&self.path).exists() {
+            return Ok(Vec::new());
+        }
+        let content = fs::read_to_string(&self.path).map_err(|x| {
+            BatteryError::ReadFile(self.path.to_string_lossy().to_string(), x.to_string())
+        })?;
+        Ok(content.lines().map(|x| x.to_string()).collect())
+    }
+
+    fn write_error(&self, error: std::io::Error) -> BatteryError {
+        BatteryError::WriteFile(self.path.to_string_lossy().to_string(), error.to_string())
+    }
+}
+
+fn unix_time(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|x| x.as_secs())
+        .unwrap_or_default()
+}
+
+pub fn get_history(config: &Configuration, since: Duration) -> Result<String, BatteryError> {
+    let samples = History::new(config).since(since)?;
+    Ok(serde_json::to_string(&samples).unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::configuration::{Battery as BatteryConfiguration, BatteryHistory};
+    use tempfile::TempDir;
+
+    fn setup_history(max_samples: usize) -> (TempDir, History) {
+        let temp_dir = TempDir::new().unwrap();
+        let config = Configuration {
+            battery: BatteryConfiguration {
+                history: BatteryHistory {
+                    path: Some(
+                        temp_dir
+                            .path()
+                            .join("battery.csv")
+                            .to_string_lossy()
+                            .to_string(),
+                    ),
+                    max_samples,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        (temp_dir, History::new(&config))
+    }
+
+    #[test]
+    fn test_history_roundtrip() {
+        let (_temp_dir, history) = setup_history(100);
+        let sample = Sample::now(42, BatteryStatus::NotCharging, Some(7.5));
+        history.append(&sample).unwrap();
+        history
+            .append(&Sample {
+                timestamp: 0,
+                capacity: 99,
+                status: BatteryStatus::Full,
+                power: None,
+            })
+            .unwrap();
+
+        let samples = history.since(Duration::from_secs(3600)).unwrap();
+        assert_eq!(samples, vec![sample]);
+    }
+
+    #[test]
+    fn test_history_is_bounded() {
+        let (_temp_dir, history) = setup_history(10);
+        for capacity in 0..50 {
+            history
+                .append(&Sample::now(capacity, BatteryStatus::Discharging, None))
+                .unwrap();
+        }
+        let samples = history.since(Duration::from_secs(3600)).unwrap();
+        assert!(samples.len() <= 11);
+        assert_eq!(samples.last().unwrap().capacity, 49);
+    }
+
+    #[test]
+    fn test_history_counts_existing_lines() {
+        let (_temp_dir, history) = setup_history(10);
+        let line = Sample::now(1, BatteryStatus::Discharging, None).to_line();
+        fs::write(&history.path, format!("{line}\n").repeat(30)).unwrap();
+        history
+            .append(&Sample::now(2, BatteryStatus::Discharging, None))
+            .unwrap();
+        assert_eq!(history.read_lines().unwrap().len(), 10);
+        // Counted, not read
+        history
+            .append(&Sample::now(3, BatteryStatus::Discharging, None))
+            .unwrap();
+        assert_eq!(*history.lines.lock().unwrap(), Some(11));
+        assert_eq!(history.read_lines().unwrap().len(), 11);
+    }
+}
@@ -1,24 +1,217 @@
+use hyprland::data::{Clients, Workspace};
 use hyprland::event_listener::EventListener;
-use log::info;
-use std::{sync::Arc, time::Duration};
+use hyprland::prelude::*;
+use hyprland::shared::Address;
+use log::{debug, error, info};
+use notify_rust::Notification;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 
-use crate::{configuration::Configuration, workspace::eww_workspace_update};
+use crate::{
+    configuration::{Configuration, Hyprland},
+    error::WindowError,
+    eww::{eww_update, EwwVariable},
+    keyboard::eww_keyboard_update,
+    window::{eww_window_update, Titles},
+    workspace::eww_workspace_update,
+};
 
-pub fn listener(config: Arc<Configuration>) -> EventListener {
+/// Windows which demand attention until their workspace is focused
+pub(crate) type Urgent = Arc<Mutex<HashSet<Address>>>;
+
+fn update_workspaces(hyprland: &Hyprland, urgent: &Urgent) {
+    let urgent = urgent.lock().unwrap().clone();
+    if let Err(err) =
+        eww_workspace_update(hyprland, hyprland.default_spaces, hyprland.format, &urgent)
+    {
+        error!("{err}");
+    }
+}
+
+/// Marks the window as urgent, unless its workspace is already focused
+fn urgent_window(hyprland: &Hyprland, urgent: &Urgent, address: Address) {
+    let clients = match Clients::get() {
+        Ok(clients) => clients.to_vec(),
+        Err(err) => {
+            error!("Unable to get the urgent window: {err}");
+            return;
+        }
+    };
+    let Some(client) = clients.iter().find(|x| x.address == address) else {
+        return;
+    };
+    if Workspace::get_active().is_ok_and(|workspace| workspace.id == client.workspace.id) {
+        return;
+    }
+    info!("Window {} is urgent", client.class);
+    urgent.lock().unwrap().insert(address);
+    if hyprland.urgent_notification {
+        let result = Notification::new()
+            .summary(&client.class)
+            .body(&client.title)
+            .show();
+        if let Err(err) = result {
+            error!("Unable to send notification: {err:#?}");
+        }
+    }
+}
+
+/// Clears the urgency of the windows on the focused workspace
+fn focus_workspace(urgent: &Urgent, workspace: i32) {
+    let mut urgent = urgent.lock().unwrap();
+    if urgent.is_empty() {
+        return;
+    }
+    match Clients::get() {
+        Ok(clients) => urgent.retain(|address| {
+            !clients
+                .iter()
+                .any(|x| &x.address == address && x.workspace.id == workspace)
+        }),
+        Err(err) => debug!("Unable to clear urgent windows: {err}"),
+    }
+}
+
+fn update_window(titles: &Titles) {
+    if let Err(err) = eww_window_update(titles) {
+        error!("{err}");
+    }
+}
+
+/// Active keybind submap, e.g. `resize`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Submap {
+    name: String,
+    /// False for the default submap
+    active: bool,
+}
+
+impl From<String> for Submap {
+    fn from(name: String) -> Self {
+        Self {
+            active: !name.is_empty(),
+            name,
+        }
+    }
+}
+
+/// Updates eww on Hyprland events, `urgent` is written by the listener only
+pub fn listener(config: Arc<Configuration>, urgent: Urgent) -> Result<EventListener, WindowError> {
+    let titles =
+        Arc::new(Titles::new(&config.window).map_err(|err| WindowError::Rewrite(err.to_string()))?);
     let mut listener = EventListener::new();
-    let default_spaces = config.hyprland.default_spaces;
-    listener.add_workspace_changed_handler(move |_| {
+    let (hyprland, state) = (config.hyprland.clone(), urgent.clone());
+    listener.add_workspace_changed_handler(move |data| {
         info!("Workspace Switch");
-        eww_workspace_update(default_spaces).expect("Unable to update workspace!")
+        focus_workspace(&state, data.id);
+        update_workspaces(&hyprland, &state)
+    });
+    let (hyprland, state) = (config.hyprland.clone(), urgent.clone());
+    listener.add_urgent_state_changed_handler(move |address| {
+        urgent_window(&hyprland, &state, address);
+        update_workspaces(&hyprland, &state)
     });
+    let (hyprland, state) = (config.hyprland.clone(), urgent.clone());
+    listener.add_active_monitor_changed_handler(move |data| {
+        info!("Monitor {} is focused", data.monitor_name);
+        update_workspaces(&hyprland, &state)
+    });
+    let (hyprland, state) = (config.hyprland.clone(), urgent.clone());
+    listener.add_workspace_moved_handler(move |data| {
+        info!("Workspace {} moved to {}", data.id, data.monitor);
+        update_workspaces(&hyprland, &state)
+    });
+    let (hyprland, state) = (config.hyprland.clone(), urgent.clone());
+    listener.add_workspace_added_handler(move |data| {
+        info!("Workspace {} is added", data.id);
+        update_workspaces(&hyprland, &state)
+    });
+    let (hyprland, state) = (config.hyprland.clone(), urgent.clone());
+    listener.add_workspace_deleted_handler(move |data| {
+        info!("Workspace {} is removed", data.id);
+        update_workspaces(&hyprland, &state)
+    });
+    let (hyprland, state) = (config.hyprland.clone(), urgent.clone());
+    listener.add_workspace_renamed_handler(move |data| {
+        info!("Workspace {} is renamed to {}", data.id, data.name);
+        update_workspaces(&hyprland, &state)
+    });
+    let (hyprland, state) = (config.hyprland.clone(), urgent.clone());
+    listener.add_changed_special_handler(move |data| {
+        info!("Special workspace {} is shown", data.workspace_name);
+        update_workspaces(&hyprland, &state)
+    });
+    let (hyprland, state) = (config.hyprland.clone(), urgent.clone());
+    listener.add_special_removed_handler(move |monitor| {
+        info!("Special workspace on {monitor} is hidden");
+        update_workspaces(&hyprland, &state)
+    });
+    let (hyprland, state) = (config.hyprland.clone(), urgent.clone());
+    listener.add_window_opened_handler(move |data| {
+        info!(
+            "Window {} is opened on {}",
+            data.window_class, data.workspace_name
+        );
+        update_workspaces(&hyprland, &state)
+    });
+    let (hyprland, state) = (config.hyprland.clone(), urgent.clone());
+    listener.add_window_closed_handler(move |address| {
+        info!("Window {address} is closed");
+        state.lock().unwrap().remove(&address);
+        update_workspaces(&hyprland, &state)
+    });
+    let (hyprland, state) = (config.hyprland.clone(), urgent.clone());
+    listener.add_window_moved_handler(move |data| {
+        info!(
+            "Window {} is moved to {}",
+            data.window_address, data.workspace_id
+        );
+        update_workspaces(&hyprland, &state)
+    });
+    let (window, hyprland, state) = (titles.clone(), config.hyprland.clone(), urgent.clone());
+    listener.add_active_window_changed_handler(move |data| {
+        debug!("Active window {:?}", data.as_ref().map(|data| &data.class));
+        update_window(&window);
+        let focused = data.is_some_and(|data| state.lock().unwrap().remove(&data.address));
+        if focused {
+            update_workspaces(&hyprland, &state)
+        }
+    });
+    let window = titles.clone();
+    listener.add_window_title_changed_handler(move |_| update_window(&window));
+    let window = titles.clone();
+    listener.add_fullscreen_state_changed_handler(move |fullscreen| {
+        debug!("Fullscreen {fullscreen}");
+        update_window(&window)
+    });
+    let window = titles;
+    listener.add_float_state_changed_handler(move |_| update_window(&window));
+    let (hyprland, state) = (config.hyprland.clone(), urgent.clone());
     listener.add_monitor_added_handler(move |data| {
         info!("Monitor {} is added (id: {})", data.name, data.id);
         std::thread::sleep(Duration::from_secs(5));
         // wake_up(eww_config_monitor_add.clone()).expect("Unable to wake up glue!");
+        update_workspaces(&hyprland, &state)
+    });
+    listener.add_layout_changed_handler(move |data| {
+        info!("Keyboard {} uses {}", data.keyboard_name, data.layout_name);
+        if let Err(err) = eww_keyboard_update() {
+            error!("{err}");
+        }
+    });
+    listener.add_sub_map_changed_handler(move |submap| {
+        info!("Submap {submap:?}");
+        if let Err(err) = eww_update(EwwVariable::Submap(submap.into())) {
+            error!("Unable to update EWW: {err:#?}");
+        }
     });
     listener.add_monitor_removed_handler(move |data| {
         info!("Monitor {data} is removed");
         // wake_up(eww_config_monitor_remove.clone()).expect("Unable to wake up glue!");
     });
-    listener
+    Ok(listener)
 }
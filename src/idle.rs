@@ -0,0 +1,180 @@
+use std::sync::Arc;
+
+use hyprland::dispatch::{Dispatch, DispatchType};
+use log::{error, info};
+use tokio::sync::mpsc::UnboundedReceiver;
+use tokio::sync::Mutex;
+
+use crate::brightness;
+use crate::configuration::{Configuration, IdleStage};
+use crate::error::{GlueError, IdleError};
+use crate::start::run_commands;
+use crate::wayland::IdleEvent;
+use crate::DaemonState;
+
+#[derive(Debug, PartialEq)]
+enum IdleAction {
+    Dim,
+    Lock,
+    Dpms,
+    Suspend,
+    Shell(String),
+}
+
+impl From<&str> for IdleAction {
+    fn from(value: &str) -> Self {
+        match value.trim() {
+            "dim" => Self::Dim,
+            "lock" => Self::Lock,
+            "dpms" => Self::Dpms,
+            "suspend" => Self::Suspend,
+            command => Self::Shell(command.to_string()),
+        }
+    }
+}
+
+/// What has to be undone when the user is active again
+enum Undo {
+    Dim(Vec<(String, u32)>),
+    Dpms,
+    Nothing,
+}
+
+impl IdleAction {
+    fn run(&self, config: &Configuration) -> Result<Undo, GlueError> {
+        match self {
+            IdleAction::Dim => brightness::dim(config, config.idle.dim).map(Undo::Dim),
+            IdleAction::Lock => run_commands(vec![&config.idle.lock]).map(|()| Undo::Nothing),
+            IdleAction::Dpms => dpms(false).map(|()| Undo::Dpms),
+            IdleAction::Suspend => suspend().map(|()| Undo::Nothing),
+            IdleAction::Shell(command) => shell(command).map(|()| Undo::Nothing),
+        }
+    }
+}
+
+impl Undo {
+    fn run(self, config: &Configuration) -> Result<(), GlueError> {
+        match self {
+            Undo::Dim(saved) => brightness::restore(config, &saved),
+            Undo::Dpms => dpms(true),
+            Undo::Nothing => Ok(()),
+        }
+    }
+}
+
+fn dpms(on: bool) -> Result<(), GlueError> {
+    Dispatch::call(DispatchType::ToggleDPMS(on, None))
+        .map_err(|err| GlueError::Idle(IdleError::Dpms(err.to_string())))
+}
+
+fn suspend() -> Result<(), GlueError> {
+    let logind = |err: zbus::Error| GlueError::Idle(IdleError::Logind(err.to_string()));
+    let bus = zbus::blocking::Connection::system().map_err(logind)?;
+    bus.call_method(
+        Some("org.freedesktop.login1"),
+        "/org/freedesktop/login1",
+        Some("org.freedesktop.login1.Manager"),
+        "Suspend",
+        &(false),
+    )
+    .map_err(logind)?;
+    Ok(())
+}
+
+fn shell(command: &str) -> Result<(), GlueError> {
+    std::process::Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .spawn()
+        .map(|_| ())
+        .map_err(|err| GlueError::Idle(IdleError::Shell(command.to_string(), err.to_string())))
+}
+
+/// Runs an action on the blocking pool, they write sysfs, call hyprctl or wait for D-Bus
+async fn blocking<T: Send + 'static>(
+    action: impl FnOnce() -> Result<T, GlueError> + Send + 'static,
+) -> Result<T, GlueError> {
+    tokio::task::spawn_blocking(action)
+        .await
+        .unwrap_or_else(|err| Err(GlueError::Idle(IdleError::Task(err.to_string()))))
+}
+
+/// Runs the `[[idle.stage]]` actions, the events are indexed like the stages
+pub(crate) async fn idle(
+    config: Arc<Configuration>,
+    state: Arc<Mutex<DaemonState>>,
+    mut events: UnboundedReceiver<IdleEvent>,
+) {
+    let stages: &[IdleStage] = &config.idle.stage;
+    let mut active: Vec<Option<Undo>> = stages.iter().map(|_| None).collect();
+    let manual_brightness = state.lock().await.manual_brightness.clone();
+    while let Some(event) = events.recv().await {
+        match event {
+            IdleEvent::Idled(index) => {
+                let Some(stage) = stages.get(index) else {
+                    continue;
+                };
+                if state.lock().await.inhibited() {
+                    info!("Idle: coffee is active, skipping {}", stage.on_idle);
+                    continue;
+                }
+                info!("Idle: running {}", stage.on_idle);
+                let action = IdleAction::from(stage.on_idle.as_str());
+                // The ambient light sensor must not brighten the dimmed screen
+                let dims = action == IdleAction::Dim;
+                if dims {
+                    manual_brightness.dim(true);
+                }
+                let config = config.clone();
+                match blocking(move || action.run(&config)).await {
+                    Ok(undo) => active[index] = Some(undo),
+                    Err(err) => {
+                        if dims {
+                            manual_brightness.dim(false);
+                        }
+                        error!("Idle: {err}");
+                    }
+                }
+            }
+            IdleEvent::Resumed(index) => {
+                let Some(undo) = active.get_mut(index).and_then(Option::take) else {
+                    continue;
+                };
+                info!("Idle: resuming {}", stages[index].on_idle);
+                let undims = matches!(undo, Undo::Dim(_));
+                let resume = stages[index].on_resume.clone();
+                let config = config.clone();
+                let result = blocking(move || {
+                    // The resume command runs even if the undo failed
+                    let undone = undo.run(&config);
+                    match &resume {
+                        Some(command) => undone.and(shell(command)),
+                        None => undone,
+                    }
+                })
+                .await;
+                if undims {
+                    manual_brightness.dim(false);
+                }
+                if let Err(err) = result {
+                    error!("Idle: {err}");
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_action() {
+        assert_eq!(IdleAction::from("dim"), IdleAction::Dim);
+        assert_eq!(IdleAction::from(" dpms "), IdleAction::Dpms);
+        assert_eq!(
+            IdleAction::from("notify-send idle"),
+            IdleAction::Shell("notify-send idle".to_string())
+        );
+    }
+}
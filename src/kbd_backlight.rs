@@ -0,0 +1,242 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use log::{error, info};
+use serde::Serialize;
+use tokio::sync::mpsc::UnboundedReceiver;
+
+use crate::configuration::Configuration;
+use crate::error::{GlueError, KbdBacklightError};
+use crate::eww::{eww_update, EwwVariable};
+use crate::key::Changeable;
+use crate::utils::glob_match;
+use crate::wayland::IdleEvent;
+use crate::Change;
+
+/// Keyboard backlight in `/sys/class/leds`, controlled in the levels of the device
+pub(crate) struct KbdBacklight {
+    name: String,
+    path: PathBuf,
+    brightness: u32,
+    max: u32,
+}
+
+#[derive(Serialize, Clone)]
+pub(crate) struct KbdBacklightState {
+    name: String,
+    brightness: u32,
+    max: u32,
+    /// Brightness relative to `max` (in %)
+    percent: u32,
+}
+
+impl From<&KbdBacklight> for KbdBacklightState {
+    fn from(value: &KbdBacklight) -> Self {
+        Self {
+            name: value.name.clone(),
+            brightness: value.brightness,
+            max: value.max,
+            percent: match value.max {
+                0 => 0,
+                max => value.brightness * 100 / max,
+            },
+        }
+    }
+}
+
+impl Changeable<u32> for KbdBacklight {
+    fn change(&mut self, change: Change<u32>) -> Result<(), GlueError> {
+        let brightness = match change {
+            Change::Add(update) => (self.brightness + update).min(self.max),
+            Change::Sub(div) => self.brightness.saturating_sub(div),
+            Change::Absolute(value) => value.min(self.max),
+        };
+        self.write(brightness).map_err(GlueError::KbdBacklight)?;
+        self.brightness = brightness;
+        eww_update(EwwVariable::KbdBacklight((&*self).into())).map_err(GlueError::Command)
+    }
+}
+
+impl KbdBacklight {
+    fn try_new(config: &Configuration) -> Result<Self, KbdBacklightError> {
+        let leds = &config.kbd_backlight.path;
+        let mut devices = std::fs::read_dir(leds)
+            .map_err(|x| KbdBacklightError::ReadFile(leds.clone(), x.to_string()))?
+            .flatten()
+            .filter(|entry| {
+                glob_match(
+                    &config.kbd_backlight.device,
+                    &entry.file_name().to_string_lossy(),
+                )
+            })
+            .map(|entry| entry.path())
+            .collect::<Vec<_>>();
+        devices.sort();
+        let path = devices
+            .into_iter()
+            .next()
+            .ok_or(KbdBacklightError::NotFound(leds.clone()))?;
+        Ok(Self {
+            name: path
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            brightness: Self::read(&path.join("brightness"))?,
+            max: Self::read(&path.join("max_brightness"))?,
+            path,
+        })
+    }
+
+    fn read(path: &Path) -> Result<u32, KbdBacklightError> {
+        std::fs::read_to_string(path)
+            .map_err(|x| {
+                KbdBacklightError::ReadFile(path.to_string_lossy().to_string(), x.to_string())
+            })?
+            .trim_end()
+            .parse::<u32>()
+            .map_err(|x| KbdBacklightError::Parse(x.to_string()))
+    }
+
+    /// Writes the brightness file, falling back to logind for users without write permission
+    fn write(&self, brightness: u32) -> Result<(), KbdBacklightError> {
+        let path = self.path.join("brightness");
+        match std::fs::write(&path, brightness.to_string()) {
+            Err(err) if err.kind() == std::io::ErrorKind::PermissionDenied => {
+                let bus = zbus::blocking::Connection::system()
+                    .map_err(|x| KbdBacklightError::Logind(x.to_string()))?;
+                bus.call_method(
+                    Some("org.freedesktop.login1"),
+                    "/org/freedesktop/login1/session/auto",
+                    Some("org.freedesktop.login1.Session"),
+                    "SetBrightness",
+                    &("leds", &self.name, brightness),
+                )
+                .map_err(|x| KbdBacklightError::Logind(x.to_string()))?;
+                Ok(())
+            }
+            result => result.map_err(|x| {
+                KbdBacklightError::WriteFile(path.to_string_lossy().to_string(), x.to_string())
+            }),
+        }
+    }
+
+    pub fn get(config: &Configuration) -> Result<(), GlueError> {
+        let backlight = Self::try_new(config).map_err(GlueError::KbdBacklight)?;
+        print!(
+            "{}",
+            serde_json::to_string(&KbdBacklightState::from(&backlight)).unwrap()
+        );
+        Ok(())
+    }
+
+    pub fn set(config: &Configuration, level: u32) -> Result<(), GlueError> {
+        Self::try_new(config)
+            .map_err(GlueError::KbdBacklight)?
+            .change(Change::Absolute(level))
+    }
+
+    pub fn increase(config: &Configuration) -> Result<(), GlueError> {
+        Self::try_new(config)
+            .map_err(GlueError::KbdBacklight)?
+            .change(Change::Add(1))
+    }
+
+    pub fn decrease(config: &Configuration) -> Result<(), GlueError> {
+        Self::try_new(config)
+            .map_err(GlueError::KbdBacklight)?
+            .change(Change::Sub(1))
+    }
+
+    /// Switches between off and the highest level
+    pub fn toggle(config: &Configuration) -> Result<(), GlueError> {
+        let mut backlight = Self::try_new(config).map_err(GlueError::KbdBacklight)?;
+        let level = match backlight.brightness {
+            0 => backlight.max,
+            _ => 0,
+        };
+        backlight.change(Change::Absolute(level))
+    }
+}
+
+/// Turns the keyboard backlight off while the user is idle and restores it on activity.
+/// The sysfs, logind and eww calls run on the blocking pool.
+pub(crate) async fn idle(config: Arc<Configuration>, mut events: UnboundedReceiver<IdleEvent>) {
+    let mut restore = None;
+    while let Some(event) = events.recv().await {
+        let config = config.clone();
+        let result = tokio::task::spawn_blocking(move || on_idle(&config, event, restore))
+            .await
+            .unwrap_or_else(|err| {
+                Err(GlueError::KbdBacklight(KbdBacklightError::Task(
+                    err.to_string(),
+                )))
+            });
+        match result {
+            Ok(level) => restore = level,
+            Err(err) => error!("Keyboard Backlight: {err}"),
+        }
+    }
+}
+
+/// Handles one idle event, returning the level to restore on activity
+fn on_idle(
+    config: &Configuration,
+    event: IdleEvent,
+    restore: Option<u32>,
+) -> Result<Option<u32>, GlueError> {
+    let mut backlight = KbdBacklight::try_new(config).map_err(GlueError::KbdBacklight)?;
+    match event {
+        IdleEvent::Idled(_) if backlight.brightness > 0 => {
+            info!("Keyboard Backlight: idle, turning off");
+            let level = backlight.brightness;
+            backlight.change(Change::Absolute(0))?;
+            Ok(Some(level))
+        }
+        IdleEvent::Resumed(_) => match restore {
+            // The user might have changed the backlight while being idle
+            Some(level) if backlight.brightness == 0 => {
+                info!("Keyboard Backlight: active, restoring level {level}");
+                backlight.change(Change::Absolute(level))?;
+                Ok(None)
+            }
+            _ => Ok(None),
+        },
+        _ => Ok(restore),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn setup_test_environment() -> (TempDir, Configuration) {
+        let temp_dir = TempDir::new().unwrap();
+        let device = temp_dir.path().join("tpacpi::kbd_backlight");
+        std::fs::create_dir_all(&device).unwrap();
+        std::fs::create_dir_all(temp_dir.path().join("input3::capslock")).unwrap();
+        std::fs::write(device.join("brightness"), "1\n").unwrap();
+        std::fs::write(device.join("max_brightness"), "2\n").unwrap();
+
+        let mut config = Configuration::default();
+        config.kbd_backlight.path = temp_dir.path().to_string_lossy().to_string();
+        (temp_dir, config)
+    }
+
+    #[test]
+    fn test_kbd_backlight_change() {
+        let (_temp_dir, config) = setup_test_environment();
+        let backlight = KbdBacklight::try_new(&config).unwrap();
+        assert_eq!(backlight.name, "tpacpi::kbd_backlight");
+        assert_eq!(backlight.brightness, 1);
+
+        KbdBacklight::increase(&config).unwrap();
+        KbdBacklight::increase(&config).unwrap();
+        assert_eq!(KbdBacklight::try_new(&config).unwrap().brightness, 2);
+
+        KbdBacklight::toggle(&config).unwrap();
+        assert_eq!(KbdBacklight::try_new(&config).unwrap().brightness, 0);
+        KbdBacklight::toggle(&config).unwrap();
+        assert_eq!(KbdBacklight::try_new(&config).unwrap().brightness, 2);
+    }
+}
@@ -0,0 +1,95 @@
+use hyprland::ctl::switch_xkb_layout::{self, SwitchXKBLayoutCmdTypes};
+use hyprland::data::{Devices, Keyboard};
+use hyprland::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::error::KeyboardError;
+use crate::eww::{eww_update, EwwVariable};
+
+/// Layout of the main keyboard
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct KeyboardState {
+    keyboard: String,
+    /// Active keymap, e.g. `English (US)`
+    keymap: String,
+    /// Configured xkb layouts, e.g. `["us", "de"]`
+    layouts: Vec<String>,
+}
+
+impl From<Keyboard> for KeyboardState {
+    fn from(value: Keyboard) -> Self {
+        Self {
+            layouts: layouts(&value),
+            keyboard: value.name,
+            keymap: value.active_keymap,
+        }
+    }
+}
+
+fn layouts(keyboard: &Keyboard) -> Vec<String> {
+    keyboard
+        .layout
+        .split(',')
+        .map(|layout| layout.trim().to_string())
+        .filter(|layout| !layout.is_empty())
+        .collect()
+}
+
+fn main_keyboard() -> Result<Keyboard, KeyboardError> {
+    Devices::get()
+        .map_err(KeyboardError::Hyprland)?
+        .keyboards
+        .into_iter()
+        .find(|keyboard| keyboard.main)
+        .ok_or(KeyboardError::NotFound)
+}
+
+/// Index of `layout` in the layouts of the keyboard, `layout` may be the index itself
+fn layout_index(layouts: &[String], layout: &str) -> Option<u8> {
+    match layouts.iter().position(|x| x == layout) {
+        Some(index) => u8::try_from(index).ok(),
+        None => layout
+            .parse::<u8>()
+            .ok()
+            .filter(|index| usize::from(*index) < layouts.len()),
+    }
+}
+
+pub fn get() -> Result<(), KeyboardError> {
+    let state = KeyboardState::from(main_keyboard()?);
+    println!("{}", serde_json::to_string(&state).unwrap());
+    Ok(())
+}
+
+pub fn next() -> Result<(), KeyboardError> {
+    let keyboard = main_keyboard()?;
+    switch_xkb_layout::call(keyboard.name, SwitchXKBLayoutCmdTypes::Next)
+        .map_err(KeyboardError::Hyprland)
+}
+
+pub fn set(layout: &str) -> Result<(), KeyboardError> {
+    let keyboard = main_keyboard()?;
+    let index = layout_index(&layouts(&keyboard), layout)
+        .ok_or_else(|| KeyboardError::UnknownLayout(layout.to_string()))?;
+    switch_xkb_layout::call(keyboard.name, SwitchXKBLayoutCmdTypes::Id(index))
+        .map_err(KeyboardError::Hyprland)
+}
+
+pub fn eww_keyboard_update() -> Result<(), KeyboardError> {
+    let state = KeyboardState::from(main_keyboard()?);
+    eww_update(EwwVariable::Keyboard(state)).map_err(KeyboardError::Command)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_layout_index() {
+        let layouts = ["us".to_string(), "de".to_string()];
+        assert_eq!(layout_index(&layouts, "de"), Some(1));
+        assert_eq!(layout_index(&layouts, "0"), Some(0));
+        assert_eq!(layout_index(&layouts, "2"), None);
+        assert_eq!(layout_index(&layouts, "fr"), None);
+    }
+}
@@ -0,0 +1,54 @@
+use std::os::fd::OwnedFd;
+
+use log::info;
+
+use crate::error::CoffeeError;
+
+/// Inhibitor lock of systemd-logind, held as long as the file descriptor is open
+#[derive(Debug)]
+pub struct LogindInhibitor {
+    /// Colon separated what-values, e.g. `idle:sleep:handle-lid-switch`
+    what: String,
+    fd: Option<OwnedFd>,
+}
+
+impl LogindInhibitor {
+    pub fn new(what: &[String]) -> Self {
+        Self {
+            what: what.join(":"),
+            fd: None,
+        }
+    }
+
+    pub async fn inhibit(&mut self) -> Result<(), CoffeeError> {
+        if self.fd.is_some() {
+            return Ok(());
+        }
+        let logind = |err: zbus::Error| CoffeeError::Logind(err.to_string());
+        let bus = zbus::Connection::system().await.map_err(logind)?;
+        let reply = bus
+            .call_method(
+                Some("org.freedesktop.login1"),
+                "/org/freedesktop/login1",
+                Some("org.freedesktop.login1.Manager"),
+                "Inhibit",
+                &(self.what.as_str(), "glue", "Coffee", "block"),
+            )
+            .await
+            .map_err(logind)?;
+        let fd: zbus::zvariant::OwnedFd = reply.body().deserialize().map_err(logind)?;
+        self.fd = Some(fd.into());
+        info!(target: "LogindInhibitor", "Inhibitor lock for {} was TAKEN", self.what);
+        Ok(())
+    }
+
+    pub fn release(&mut self) {
+        if self.fd.take().is_some() {
+            info!(target: "LogindInhibitor", "Inhibitor lock for {} was RELEASED", self.what);
+        }
+    }
+
+    pub fn is_held(&self) -> bool {
+        self.fd.is_some()
+    }
+}
@@ -1,5 +1,5 @@
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, SystemTime};
 
 use anyhow::Result;
 use commands::Notification;
@@ -7,21 +7,29 @@ use key::{FunctionKey, MuteKey};
 use serde::{Deserialize, Serialize};
 use tracing::error;
 
+use ambient::ManualBrightness;
+use brightness::Transitions;
 use clap::Parser;
+use coffee::{Inhibitors, Reason};
 use glue::bin_name;
+use logind::LogindInhibitor;
+use night::NightLight;
 use utils::CancelableTimer;
 use wayland::{WaylandClient, WaylandIdle};
 
 use self::audio::{get_audio, set_audio};
-use self::battery::get_battery;
+use self::battery::{get_ac, get_battery, get_charge_limit, get_health, set_charge_limit};
 use self::cli::{AudioCommand, Cli, Command::*, MicCommand, WorkspaceCommand};
-use self::configuration::Configuration;
+use self::configuration::{Configuration, InhibitBackend};
 use self::daemon::daemon;
 use self::error::{DaemonError, GlueError};
+use self::history::get_history;
+use self::hyprland::Urgent;
 use self::mic::{get_mic, toggle_mic};
 use self::start::run_commands;
-use self::workspace::{eww_workspace_update, eww_workspaces};
+use self::workspace::{eww_workspace_update, eww_workspaces, urgent};
 
+mod ambient;
 mod audio;
 mod autostart;
 mod battery;
@@ -31,15 +39,24 @@ mod coffee;
 mod commands;
 mod configuration;
 mod daemon;
+mod desktop;
 mod error;
 mod eww;
+mod history;
 mod hyprland;
+mod idle;
+mod kbd_backlight;
 mod key;
+mod keyboard;
+mod logind;
 mod mic;
 mod monitor;
+mod night;
+mod rules;
 mod start;
 mod utils;
 mod wayland;
+mod window;
 mod workspace;
 
 pub const GLUE_PATH: &str = "/tmp/glue.sock";
@@ -77,16 +94,31 @@ fn main() -> Result<()> {
         } => daemon(&config, eww_config, no_autostart).map_err(GlueError::Daemon),
         Workspace {
             default_spaces,
+            format,
+            monitor,
             command,
         } => match command {
-            None => eww_workspaces(default_spaces)
-                .map_err(GlueError::Workspace)
-                .map(|x| {
-                    print!("{x}");
-                }),
-            Some(WorkspaceCommand::Update { default_spaces }) => {
-                eww_workspace_update(default_spaces).map_err(GlueError::Workspace)
-            }
+            None => eww_workspaces(
+                &config.hyprland,
+                default_spaces,
+                format.unwrap_or(config.hyprland.format),
+                monitor.as_deref(),
+                &urgent(),
+            )
+            .map_err(GlueError::Workspace)
+            .map(|x| {
+                print!("{x}");
+            }),
+            Some(WorkspaceCommand::Update {
+                default_spaces,
+                format,
+            }) => eww_workspace_update(
+                &config.hyprland,
+                default_spaces,
+                format.unwrap_or(config.hyprland.format),
+                &urgent(),
+            )
+            .map_err(GlueError::Workspace),
         },
         Audio { command } => match command {
             AudioCommand::Set { percent } => set_audio(percent),
@@ -108,17 +140,80 @@ fn main() -> Result<()> {
                 }
                 Err(err) => Err(GlueError::Battery(err)),
             },
+            cli::BatteryCommand::History { since } => get_history(&config, since.into())
+                .map(|result| {
+                    println!("{result}");
+                })
+                .map_err(GlueError::Battery),
+            cli::BatteryCommand::Health => get_health(&config)
+                .map(|result| {
+                    println!("{result}");
+                })
+                .map_err(GlueError::Battery),
+            cli::BatteryCommand::Limit { command } => match command {
+                cli::LimitCommand::Get => get_charge_limit(&config).map(|result| {
+                    println!("{result}");
+                }),
+                cli::LimitCommand::Set { thresholds } => match thresholds[..] {
+                    [end] => set_charge_limit(&config, None, end),
+                    [start, end] => set_charge_limit(&config, Some(start), end),
+                    _ => unreachable!("clap takes one or two thresholds"),
+                },
+            }
+            .map_err(GlueError::Battery),
         },
+        Power { command } => match command {
+            cli::PowerCommand::Ac => get_ac(&config).map(|result| {
+                println!("{result}");
+            }),
+        }
+        .map_err(GlueError::Battery),
         Start {} => start(),
         WakeUp { eww_config } => wake_up(eww_config),
         Lock {} => lock(),
-        Coffee { command } => coffee::client(command.into(), &config).map_err(GlueError::Coffee),
+        Coffee {
+            command: cli::CoffeeCommand::Run { reason, command },
+        } => coffee::run(command, reason)
+            .map(|code| std::process::exit(code))
+            .map_err(GlueError::Coffee),
+        Coffee { command } => commands::Coffee::try_from(command)
+            .and_then(|command| coffee::client(command, &config))
+            .map_err(GlueError::Coffee),
         Brightness { command } => match command {
-            cli::BrightnessCommand::Get => brightness::BrightnessCtl::get(),
-            cli::BrightnessCommand::Increase => brightness::BrightnessCtl::increase(),
-            cli::BrightnessCommand::Decrease => brightness::BrightnessCtl::decrease(),
-            cli::BrightnessCommand::Set { percent } => brightness::BrightnessCtl::set(percent),
+            cli::BrightnessCommand::Get { device } => {
+                brightness::BrightnessCtl::get(&config, device)
+            }
+            cli::BrightnessCommand::Increase { device } => {
+                brightness::client(commands::Brightness::Increase { device }, &config)
+            }
+            cli::BrightnessCommand::Decrease { device } => {
+                brightness::client(commands::Brightness::Decrease { device }, &config)
+            }
+            cli::BrightnessCommand::Set { percent, device } => {
+                brightness::client(commands::Brightness::Set { percent, device }, &config)
+            }
+            cli::BrightnessCommand::Devices => brightness::BrightnessCtl::devices(&config),
+        },
+        KbdBacklight { command } => match command {
+            cli::KbdBacklightCommand::Get => kbd_backlight::KbdBacklight::get(&config),
+            cli::KbdBacklightCommand::Set { level } => {
+                kbd_backlight::KbdBacklight::set(&config, level)
+            }
+            cli::KbdBacklightCommand::Increase => kbd_backlight::KbdBacklight::increase(&config),
+            cli::KbdBacklightCommand::Decrease => kbd_backlight::KbdBacklight::decrease(&config),
+            cli::KbdBacklightCommand::Toggle => kbd_backlight::KbdBacklight::toggle(&config),
         },
+        Night { command } => night::client(command.into()),
+        Window { command } => match command {
+            cli::WindowCommand::Get => window::client(&config.window),
+        }
+        .map_err(GlueError::Window),
+        Keyboard { command } => match command {
+            cli::KeyboardCommand::Get => keyboard::get(),
+            cli::KeyboardCommand::Next => keyboard::next(),
+            cli::KeyboardCommand::Set { layout } => keyboard::set(&layout),
+        }
+        .map_err(GlueError::Keyboard),
         Test { command } => match command {
             cli::TestCommand::Notification { text } => {
                 let res = daemon::client(commands::Command::Notification(Notification::Test(text)));
@@ -153,21 +248,36 @@ fn lock() -> Result<(), GlueError> {
 #[derive(Serialize, Deserialize, Debug)]
 pub struct IdleState {
     inhibited: bool,
+    /// Coffee requested without a reason
+    manual: bool,
+    /// Requested coffees
+    reasons: Vec<Reason>,
+    /// Active automatic coffee rules
+    rules: Vec<String>,
+    until: Option<SystemTime>,
+    /// Why the requested coffee is not enforced by the compositor
+    degraded: Option<String>,
 }
 
 impl From<&DaemonState> for IdleState {
     fn from(val: &DaemonState) -> Self {
         IdleState {
-            inhibited: val.idle_inhibited,
+            inhibited: val.wayland_idle.get().inhibited || val.logind.is_held(),
+            manual: val.inhibitors.manual(),
+            reasons: val.inhibitors.reasons(),
+            rules: val.auto_inhibit.clone(),
+            until: match val.auto_inhibit.is_empty() {
+                true => val.inhibitors.until(),
+                false => None,
+            },
+            degraded: val.degraded.clone(),
         }
     }
 }
 
 impl From<WaylandIdle> for IdleState {
     fn from(value: WaylandIdle) -> Self {
-        IdleState {
-            inhibited: value.inhibited,
-        }
+        IdleState::from(&value)
     }
 }
 
@@ -175,26 +285,58 @@ impl From<&WaylandIdle> for IdleState {
     fn from(value: &WaylandIdle) -> Self {
         IdleState {
             inhibited: value.inhibited,
+            manual: value.inhibited,
+            reasons: Vec::new(),
+            rules: Vec::new(),
+            until: None,
+            degraded: None,
         }
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Debug)]
 struct DaemonState {
     wayland_idle: WaylandClient,
+    /// Connection of `wayland_idle`, replaced when it reconnects
+    wayland_connection: tokio::sync::watch::Sender<wayland_client::Connection>,
+    /// The Wayland connection is lost or lacks the idle inhibitor
+    degraded: Option<String>,
+    backend: InhibitBackend,
+    logind: LogindInhibitor,
     notification: Option<Duration>,
     idle_notify: Option<CancelableTimer>,
-    idle_inhibited: bool,
+    inhibitors: Inhibitors,
+    auto_inhibit: Vec<String>,
+    brightness_transition: Transitions,
+    manual_brightness: ManualBrightness,
+    night: NightLight,
+    urgent: Urgent,
 }
 
 impl DaemonState {
     fn new(config: Arc<Configuration>) -> Result<Self, DaemonError> {
         let wayland_idle = WaylandClient::new().map_err(DaemonError::WaylandError)?;
+        let (wayland_connection, _) =
+            tokio::sync::watch::channel(wayland_idle.connection().clone());
         Ok(Self {
             wayland_idle,
+            wayland_connection,
+            degraded: None,
+            backend: config.coffee.backend,
+            logind: LogindInhibitor::new(&config.coffee.logind),
             idle_notify: None,
             notification: config.coffee.notification,
-            idle_inhibited: false,
+            inhibitors: Inhibitors::default(),
+            auto_inhibit: Vec::new(),
+            brightness_transition: Transitions::default(),
+            manual_brightness: ManualBrightness::default(),
+            night: NightLight::new(config),
+            urgent: Urgent::default(),
         })
     }
+
+    /// Any coffee or automatic rule keeps the system awake
+    fn inhibited(&self) -> bool {
+        !self.inhibitors.is_empty() || !self.auto_inhibit.is_empty()
+    }
 }
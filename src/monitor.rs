@@ -1,21 +1,37 @@
 use async_trait::async_trait;
-use std::{ops::Deref, path::Path, sync::Arc};
+use inotify::{Inotify, WatchMask};
+use std::{
+    ops::Deref,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use crate::{
-    battery::BatteryStatus,
+    battery::{power_draw, AcAdapter, BatteryStatus},
+    brightness::BrightnessSettings,
     configuration::{BatteryEvent, Configuration},
     error::{BatteryError, DaemonError, GlueError},
     eww::eww_update,
+    history::{History, Sample},
 };
-use log::{error, info};
+use log::{debug, error, info, warn};
 use notify_rust::Notification;
 use serde::Serialize;
-use tokio::{fs::OpenOptions, io::AsyncReadExt, process::Command};
+use tokio::{fs::OpenOptions, io::AsyncReadExt, process::Command, sync::Notify};
+
+/// Shortest time between two wakes of the monitor loop by inotify, e.g. every step of
+/// a brightness transition writes the watched `brightness` file
+const WATCH_DEBOUNCE: Duration = Duration::from_millis(250);
 
 #[async_trait]
 pub(crate) trait Monitor {
     async fn update(&mut self) -> Result<(), GlueError>;
     async fn event(&self);
+    /// Files which trigger an immediate update on change, instead of waiting for the next poll
+    fn watch(&self) -> Vec<PathBuf> {
+        Vec::new()
+    }
 }
 
 pub(crate) enum Event {
@@ -27,7 +43,10 @@ pub(crate) struct Battery {
     path: String,
     status: BatteryStatus,
     capacity: u8,
+    ac: Option<bool>,
     events: Vec<Event>,
+    history: Option<History>,
+    last_sample: Option<Instant>,
 }
 
 #[derive(Serialize)]
@@ -35,6 +54,7 @@ pub(crate) struct BatteryState {
     status: BatteryStatus,
     capacity: u8,
     icon: char,
+    ac: Option<bool>,
 }
 
 impl From<&Battery> for BatteryState {
@@ -43,6 +63,7 @@ impl From<&Battery> for BatteryState {
             status: value.status,
             capacity: value.capacity,
             icon: value.icon(),
+            ac: value.ac,
         }
     }
 }
@@ -53,13 +74,18 @@ impl Monitor for Battery {
         let (capacity, status) = Self::read_state(&self.path)
             .await
             .map_err(GlueError::Battery)?;
-        if (self.status != status) || (self.capacity != capacity) {
+        let ac = AcAdapter::find(&self.path)
+            .map_err(GlueError::Battery)?
+            .map(|adapter| adapter.online);
+        self.record(capacity, status);
+        if (self.status != status) || (self.capacity != capacity) || (self.ac != ac) {
             info!(
-                "capacity: {} - old: {}, status: {} - old: {}",
-                capacity, self.capacity, status, self.status
+                "capacity: {} - old: {}, status: {} - old: {}, ac: {:?} - old: {:?}",
+                capacity, self.capacity, status, self.status, ac, self.ac
             );
             self.capacity = capacity;
             self.status = status;
+            self.ac = ac;
             self.event().await;
             return eww_update(crate::eww::EwwVariable::Battery(self.deref().into()))
                 .map_err(GlueError::Command);
@@ -114,6 +140,126 @@ pub(crate) async fn monitor(services: &mut Vec<Box<dyn Monitor>>) -> Result<(),
     Ok(())
 }
 
+/// Wakes the monitor loop on inotify events of the watched files, at most once per
+/// `WATCH_DEBOUNCE`. The loop keeps polling, as not every driver notifies about changes.
+pub(crate) fn watch(paths: Vec<PathBuf>, wake: Arc<Notify>) {
+    if paths.is_empty() {
+        return;
+    }
+    let mut inotify = match Inotify::init() {
+        Ok(inotify) => inotify,
+        Err(err) => {
+            warn!("inotify is unavailable, falling back to polling: {err}");
+            return;
+        }
+    };
+    for path in &paths {
+        if let Err(err) = inotify.watches().add(path, WatchMask::MODIFY) {
+            warn!("Unable to watch {}: {err}", path.to_string_lossy());
+        }
+    }
+    std::thread::spawn(move || {
+        let mut buffer = [0; 1024];
+        loop {
+            match inotify.read_events_blocking(&mut buffer) {
+                Ok(_) => {
+                    wake.notify_one();
+                    // Events of the pause are read at once and wake the loop once more
+                    std::thread::sleep(WATCH_DEBOUNCE);
+                }
+                Err(err) => {
+                    warn!("inotify failed, falling back to polling: {err}");
+                    return;
+                }
+            }
+        }
+    });
+}
+
+/// Display backlights in sysfs, changed by glue, firmware hotkeys or other tools
+pub(crate) struct Backlight {
+    config: Arc<Configuration>,
+    /// Name and raw brightness (in %) of the selected devices
+    devices: Vec<(String, u32)>,
+}
+
+#[async_trait]
+impl Monitor for Backlight {
+    async fn update(&mut self) -> Result<(), GlueError> {
+        let devices = self.read_devices().await;
+        if devices != self.devices {
+            info!("brightness: {:?} - old: {:?}", devices, self.devices);
+            self.devices = devices;
+            return eww_update(crate::eww::EwwVariable::Brightness(
+                BrightnessSettings::from_raw(&self.devices, &self.config.brightness),
+            ))
+            .map_err(GlueError::Command);
+        }
+        Ok(())
+    }
+
+    async fn event(&self) {}
+
+    fn watch(&self) -> Vec<PathBuf> {
+        self.devices
+            .iter()
+            .flat_map(|(name, _)| {
+                let device = Path::new(&self.config.brightness.path).join(name);
+                [device.join("actual_brightness"), device.join("brightness")]
+            })
+            .filter(|path| path.exists())
+            .collect()
+    }
+}
+
+impl Backlight {
+    pub(crate) async fn new(config: Arc<Configuration>) -> Self {
+        let mut backlight = Self {
+            config,
+            devices: Vec::new(),
+        };
+        backlight.devices = backlight.read_devices().await;
+        backlight
+    }
+
+    async fn read_devices(&self) -> Vec<(String, u32)> {
+        let Ok(mut entries) = tokio::fs::read_dir(&self.config.brightness.path).await else {
+            return Vec::new();
+        };
+        let mut devices = Vec::new();
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if !self.config.brightness.selects(&name) {
+                continue;
+            }
+            match Self::read_brightness(&entry.path()).await {
+                Some(brightness) => devices.push((name, brightness)),
+                None => debug!("Unable to read backlight {name}"),
+            }
+        }
+        devices.sort();
+        devices
+    }
+
+    /// Raw brightness (in %), computed the same way as the `brightness` crate does
+    async fn read_brightness(path: &Path) -> Option<u32> {
+        let read = |filename: &'static str| async move {
+            tokio::fs::read_to_string(path.join(filename))
+                .await
+                .ok()?
+                .trim_end()
+                .parse::<u64>()
+                .ok()
+        };
+        let max = read("max_brightness").await?;
+        let actual = read("actual_brightness").await?;
+        Some(match max {
+            0 => 0,
+            max => (actual * 100 / max) as u32,
+        })
+    }
+}
+
 type BatteryCapacity = u8;
 
 impl Battery {
@@ -135,11 +281,37 @@ impl Battery {
             path: config.battery.path.clone(),
             status: BatteryStatus::Empty,
             capacity: 0,
+            ac: None,
+            history: config
+                .battery
+                .history
+                .enabled
+                .then(|| History::new(&config)),
+            last_sample: None,
             config,
             events,
         })
     }
 
+    /// Appends a sample to the history, once the configured interval elapsed
+    fn record(&mut self, capacity: BatteryCapacity, status: BatteryStatus) {
+        let Some(history) = &self.history else {
+            return;
+        };
+        let interval = self.config.battery.history.interval;
+        if self
+            .last_sample
+            .is_some_and(|last| last.elapsed() < interval)
+        {
+            return;
+        }
+        self.last_sample = Some(Instant::now());
+        let sample = Sample::now(capacity, status, power_draw(&self.path));
+        if let Err(err) = history.append(&sample) {
+            error!("Unable to record battery history: {err}");
+        }
+    }
+
     async fn read_state(path: &str) -> Result<(BatteryCapacity, BatteryStatus), BatteryError> {
         let (raw_capacity, raw_status) = tokio::try_join!(
             Self::read_sys(path, "capacity"),
@@ -189,7 +361,9 @@ mod tests {
     use std::fs::File;
 
     use super::*;
-    use crate::configuration::{Battery as BatteryConfiguration, Configuration, Events};
+    use crate::configuration::{
+        Battery as BatteryConfiguration, BatteryHistory, Configuration, Events,
+    };
     use std::io::Write;
     use tempfile::TempDir;
 
@@ -208,6 +382,10 @@ mod tests {
         let config = Configuration {
             battery: BatteryConfiguration {
                 path: bat_dir.to_string_lossy().to_string(),
+                history: BatteryHistory {
+                    enabled: false,
+                    ..Default::default()
+                },
                 ..Default::default()
             },
             event: Some(Events {
@@ -225,6 +403,25 @@ mod tests {
         (config, capacity_file, temp_dir)
     }
 
+    #[tokio::test]
+    async fn test_backlight_change() {
+        let temp_dir = TempDir::new().unwrap();
+        let device = temp_dir.path().join("intel_backlight");
+        std::fs::create_dir_all(&device).unwrap();
+        std::fs::write(device.join("max_brightness"), "400\n").unwrap();
+        std::fs::write(device.join("actual_brightness"), "200\n").unwrap();
+        let mut config = Configuration::default();
+        config.brightness.path = temp_dir.path().to_string_lossy().to_string();
+
+        let mut backlight = Backlight::new(config.into()).await;
+        assert_eq!(backlight.devices, vec![("intel_backlight".to_string(), 50)]);
+        assert_eq!(backlight.watch(), vec![device.join("actual_brightness")]);
+
+        std::fs::write(device.join("actual_brightness"), "100\n").unwrap();
+        backlight.update().await.unwrap();
+        assert_eq!(backlight.devices, vec![("intel_backlight".to_string(), 25)]);
+    }
+
     #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
     async fn test_battery_notification() {
         let (config, mut capacity_file, _temp_dir) = setup_test_environment();
@@ -0,0 +1,592 @@
+use std::f64::consts::PI;
+use std::io::{Seek, Write};
+use std::os::fd::AsFd;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::{Datelike, Local, NaiveDate, NaiveTime, Timelike};
+use log::{debug, error, info, warn};
+use serde::{Deserialize, Serialize};
+use tokio::sync::{watch, Notify};
+use wayland_client::protocol::wl_output::WlOutput;
+use wayland_client::protocol::wl_registry::{self, WlRegistry};
+use wayland_client::{Connection, Dispatch, Proxy, QueueHandle};
+use wayland_protocols_wlr::gamma_control::v1::client::{
+    zwlr_gamma_control_manager_v1::ZwlrGammaControlManagerV1,
+    zwlr_gamma_control_v1::{self, ZwlrGammaControlV1},
+};
+
+use crate::commands;
+use crate::configuration::Configuration;
+use crate::daemon;
+use crate::error::GlueError;
+use crate::eww::{eww_update, EwwVariable};
+use crate::wayland::WaylandClientError;
+
+/// Temperature of an uncorrected output
+const NEUTRAL: u32 = 6500;
+const FRAME: Duration = Duration::from_millis(50);
+const MINUTES_PER_DAY: f64 = 24.0 * 60.0;
+
+struct Output {
+    name: u32,
+    output: WlOutput,
+    control: Option<ZwlrGammaControlV1>,
+    size: Option<u32>,
+    applied: Option<u32>,
+}
+
+#[derive(Default)]
+struct GammaData {
+    manager: Option<ZwlrGammaControlManagerV1>,
+    outputs: Vec<Output>,
+}
+
+impl GammaData {
+    fn control(&mut self, qhandle: &QueueHandle<Self>) {
+        let Some(manager) = &self.manager else {
+            return;
+        };
+        for output in self.outputs.iter_mut().filter(|x| x.control.is_none()) {
+            output.control = Some(manager.get_gamma_control(&output.output, qhandle, output.name));
+        }
+    }
+
+    /// Destroys the gamma controls, the compositor restores the original gamma tables
+    fn release(&mut self) {
+        for output in self.outputs.iter_mut() {
+            if let Some(control) = output.control.take() {
+                control.destroy();
+            }
+            output.size = None;
+            output.applied = None;
+        }
+    }
+
+    fn apply(&mut self, kelvin: u32) {
+        for output in self.outputs.iter_mut() {
+            let (Some(control), Some(size)) = (&output.control, output.size) else {
+                continue;
+            };
+            if output.applied == Some(kelvin) {
+                continue;
+            }
+            match ramp_file(size, kelvin) {
+                Ok(file) => {
+                    control.set_gamma(file.as_fd());
+                    output.applied = Some(kelvin);
+                }
+                Err(err) => error!(target: "NightLight", "Unable to write the gamma ramp: {err}"),
+            }
+        }
+    }
+}
+
+impl Dispatch<WlRegistry, ()> for GammaData {
+    fn event(
+        state: &mut Self,
+        proxy: &WlRegistry,
+        event: <WlRegistry as Proxy>::Event,
+        _data: &(),
+        _conn: &Connection,
+        qhandle: &QueueHandle<Self>,
+    ) {
+        match event {
+            wl_registry::Event::Global {
+                name,
+                interface,
+                version,
+            } => {
+                if interface == WlOutput::interface().name {
+                    debug!(target: "NightLight::WlRegister::Event::Global", "Adding Output {name}");
+                    state.outputs.push(Output {
+                        name,
+                        output: proxy.bind(name, version.min(1), qhandle, ()),
+                        control: None,
+                        size: None,
+                        applied: None,
+                    });
+                } else if interface == ZwlrGammaControlManagerV1::interface().name
+                    && state.manager.is_none()
+                {
+                    debug!(target: "NightLight::WlRegister::Event::Global", "Adding GammaControlManager");
+                    state.manager = Some(proxy.bind(name, version.min(1), qhandle, ()));
+                }
+                state.control(qhandle);
+            }
+            wl_registry::Event::GlobalRemove { name } => {
+                if let Some(index) = state.outputs.iter().position(|x| x.name == name) {
+                    debug!(target: "NightLight::WlRegister::Event::GlobalRemove", "Removing Output {name}");
+                    let output = state.outputs.remove(index);
+                    if let Some(control) = output.control {
+                        control.destroy();
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<WlOutput, ()> for GammaData {
+    fn event(
+        _state: &mut Self,
+        _proxy: &WlOutput,
+        _event: <WlOutput as Proxy>::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qhandle: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<ZwlrGammaControlManagerV1, ()> for GammaData {
+    fn event(
+        _state: &mut Self,
+        _proxy: &ZwlrGammaControlManagerV1,
+        _event: <ZwlrGammaControlManagerV1 as Proxy>::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qhandle: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<ZwlrGammaControlV1, u32> for GammaData {
+    fn event(
+        state: &mut Self,
+        proxy: &ZwlrGammaControlV1,
+        event: <ZwlrGammaControlV1 as Proxy>::Event,
+        name: &u32,
+        _conn: &Connection,
+        _qhandle: &QueueHandle<Self>,
+    ) {
+        let Some(output) = state.outputs.iter_mut().find(|x| x.name == *name) else {
+            return;
+        };
+        match event {
+            zwlr_gamma_control_v1::Event::GammaSize { size } => output.size = Some(size),
+            zwlr_gamma_control_v1::Event::Failed => {
+                // Another client owns the gamma of this output
+                warn!(target: "NightLight", "Gamma control of output {name} failed");
+                proxy.destroy();
+                output.size = None;
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Owns the gamma controls of all outputs on a dedicated thread and event queue,
+/// they are released when the `Gamma` is dropped
+pub(crate) struct Gamma {
+    sender: mpsc::Sender<u32>,
+}
+
+impl Gamma {
+    pub(crate) fn new(connection: &Connection) -> Result<Self, WaylandClientError> {
+        let mut event_queue = connection.new_event_queue();
+        let qhandle = event_queue.handle();
+        let mut data = GammaData::default();
+        let _registry = connection.display().get_registry(&qhandle, ());
+        // The first roundtrip announces the globals, the second the gamma sizes
+        for _ in 0..2 {
+            event_queue
+                .roundtrip(&mut data)
+                .map_err(WaylandClientError::RoundTripError)?;
+        }
+        if data.manager.is_none() {
+            return Err(WaylandClientError::Unsupported(
+                "zwlr_gamma_control_manager_v1",
+            ));
+        }
+        let (sender, receiver) = mpsc::channel();
+        std::thread::spawn(move || {
+            let mut kelvin = NEUTRAL;
+            loop {
+                match receiver.recv_timeout(Duration::from_secs(1)) {
+                    Ok(value) => kelvin = value,
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => {
+                        data.release();
+                        if let Err(err) = event_queue.flush() {
+                            error!(target: "NightLight", "Releasing the gamma control failed: {err}");
+                        }
+                        return;
+                    }
+                }
+                // Picks up added outputs, which receive the current temperature as well
+                if let Err(err) = event_queue.roundtrip(&mut data) {
+                    error!(target: "NightLight", "Dispatching gamma control failed: {err}");
+                    return;
+                }
+                data.apply(kelvin);
+                if let Err(err) = event_queue.flush() {
+                    error!(target: "NightLight", "Sending the gamma ramps failed: {err}");
+                    return;
+                }
+            }
+        });
+        Ok(Self { sender })
+    }
+
+    fn set(&self, kelvin: u32) {
+        let _ = self.sender.send(kelvin);
+    }
+}
+
+fn ramp_file(size: u32, kelvin: u32) -> std::io::Result<std::fs::File> {
+    let mut file = tempfile::tempfile()?;
+    file.write_all(&ramp(size, kelvin))?;
+    file.rewind()?;
+    Ok(file)
+}
+
+/// Gamma table with the red, green and blue ramps of `size` native endian u16 values each
+fn ramp(size: u32, kelvin: u32) -> Vec<u8> {
+    let last = size.saturating_sub(1).max(1) as f64;
+    whitepoint(kelvin)
+        .iter()
+        .flat_map(|factor| {
+            (0..size).flat_map(move |i| {
+                let value = (i as f64 / last * factor * u16::MAX as f64).round() as u16;
+                value.to_ne_bytes()
+            })
+        })
+        .collect()
+}
+
+/// Relative red, green and blue of a black body, with 6500K as white
+fn whitepoint(kelvin: u32) -> [f64; 3] {
+    let target = black_body(kelvin.clamp(1000, 40000) as f64);
+    let neutral = black_body(NEUTRAL as f64);
+    [0, 1, 2].map(|i| (target[i] / neutral[i]).min(1.0))
+}
+
+/// Approximation by Tanner Helland
+fn black_body(kelvin: f64) -> [f64; 3] {
+    let t = kelvin / 100.0;
+    let red = match t <= 66.0 {
+        true => 255.0,
+        false => 329.698_727_446 * (t - 60.0).powf(-0.133_204_759_2),
+    };
+    let green = match t <= 66.0 {
+        true => 99.470_802_586_1 * t.ln() - 161.119_568_166_1,
+        false => 288.122_169_528_3 * (t - 60.0).powf(-0.075_514_849_2),
+    };
+    let blue = if t >= 66.0 {
+        255.0
+    } else if t <= 19.0 {
+        0.0
+    } else {
+        138.517_731_223_1 * (t - 10.0).ln() - 305.044_792_730_7
+    };
+    [red, green, blue].map(|x| x.clamp(0.0, 255.0))
+}
+
+/// Sunrise and sunset in minutes after midnight UTC following the NOAA approximation,
+/// `None` during polar day and night
+fn sun_times(date: NaiveDate, latitude: f64, longitude: f64) -> Option<(f64, f64)> {
+    let gamma = 2.0 * PI / 365.0 * (date.ordinal0() as f64);
+    let equation_of_time = 229.18
+        * (0.000075 + 0.001868 * gamma.cos()
+            - 0.032077 * gamma.sin()
+            - 0.014615 * (2.0 * gamma).cos()
+            - 0.040849 * (2.0 * gamma).sin());
+    let declination = 0.006918 - 0.399912 * gamma.cos() + 0.070257 * gamma.sin()
+        - 0.006758 * (2.0 * gamma).cos()
+        + 0.000907 * (2.0 * gamma).sin()
+        - 0.002697 * (3.0 * gamma).cos()
+        + 0.00148 * (3.0 * gamma).sin();
+    let latitude = latitude.to_radians();
+    let cos_hour_angle = 90.833_f64.to_radians().cos() / (latitude.cos() * declination.cos())
+        - latitude.tan() * declination.tan();
+    if !(-1.0..=1.0).contains(&cos_hour_angle) {
+        return None;
+    }
+    let hour_angle = cos_hour_angle.acos().to_degrees();
+    let sunrise = 720.0 - 4.0 * (longitude + hour_angle) - equation_of_time;
+    let sunset = 720.0 - 4.0 * (longitude - hour_angle) - equation_of_time;
+    Some((sunrise, sunset))
+}
+
+fn local_time(date: NaiveDate, utc_minutes: f64) -> NaiveTime {
+    let midnight = date.and_time(NaiveTime::MIN).and_utc();
+    (midnight + chrono::Duration::seconds((utc_minutes * 60.0) as i64))
+        .with_timezone(&Local)
+        .time()
+}
+
+fn minutes(time: NaiveTime) -> f64 {
+    time.num_seconds_from_midnight() as f64 / 60.0
+}
+
+/// How far the night has progressed, 0 during the day and 1 once `fade` has passed after `start`
+fn night_factor(now: NaiveTime, start: NaiveTime, end: NaiveTime, fade: Duration) -> f64 {
+    let fade = fade.as_secs_f64() / 60.0;
+    let since_start = (minutes(now) - minutes(start)).rem_euclid(MINUTES_PER_DAY);
+    let since_end = (minutes(now) - minutes(end)).rem_euclid(MINUTES_PER_DAY);
+    let length = (minutes(end) - minutes(start)).rem_euclid(MINUTES_PER_DAY);
+    match (since_start < length, fade > 0.0) {
+        (true, true) => (since_start / fade).min(1.0),
+        (true, false) => 1.0,
+        (false, true) => (1.0 - since_end / fade).max(0.0),
+        (false, false) => 0.0,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum NightMode {
+    Auto,
+    On,
+    Off,
+    Set(u32),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NightState {
+    mode: String,
+    temperature: u32,
+    target: u32,
+    enabled: bool,
+    icon: char,
+}
+
+#[derive(Debug)]
+struct NightInner {
+    mode: NightMode,
+    temperature: u32,
+}
+
+/// Color temperature scheduler of the daemon
+#[derive(Debug, Clone)]
+pub(crate) struct NightLight {
+    config: Arc<Configuration>,
+    inner: Arc<Mutex<NightInner>>,
+    wake: Arc<Notify>,
+}
+
+impl NightLight {
+    pub(crate) fn new(config: Arc<Configuration>) -> Self {
+        let temperature = config.night.day;
+        let mode = match config.night.enabled {
+            true => NightMode::Auto,
+            false => NightMode::Off,
+        };
+        Self {
+            config,
+            inner: Arc::new(Mutex::new(NightInner { mode, temperature })),
+            wake: Arc::new(Notify::new()),
+        }
+    }
+
+    /// Night start and end of today, fixed times take precedence over the sun
+    fn schedule(&self) -> Option<(NaiveTime, NaiveTime)> {
+        let night = &self.config.night;
+        if let (Some(start), Some(end)) = (&night.start, &night.end) {
+            let parse = |x: &str| {
+                NaiveTime::parse_from_str(x, "%H:%M")
+                    .inspect_err(|err| error!(target: "NightLight", "Invalid time {x}: {err}"))
+                    .ok()
+            };
+            return Some((parse(start)?, parse(end)?));
+        }
+        let (Some(latitude), Some(longitude)) = (night.latitude, night.longitude) else {
+            return None;
+        };
+        let today = Local::now().date_naive();
+        let (sunrise, sunset) = sun_times(today, latitude, longitude)?;
+        Some((local_time(today, sunset), local_time(today, sunrise)))
+    }
+
+    fn target(&self, mode: NightMode) -> u32 {
+        let night = &self.config.night;
+        match mode {
+            NightMode::On => night.night,
+            NightMode::Off => night.day,
+            NightMode::Set(kelvin) => kelvin,
+            NightMode::Auto => match self.schedule() {
+                Some((start, end)) => {
+                    let factor = night_factor(Local::now().time(), start, end, night.fade);
+                    let day = night.day as f64;
+                    (day + (night.night as f64 - day) * factor).round() as u32
+                }
+                None => night.day,
+            },
+        }
+    }
+
+    fn state(&self) -> NightState {
+        let inner = self.inner.lock().unwrap();
+        let target = self.target(inner.mode);
+        let enabled = target < self.config.night.day;
+        NightState {
+            mode: match inner.mode {
+                NightMode::Auto => "auto",
+                NightMode::On => "on",
+                NightMode::Off => "off",
+                NightMode::Set(_) => "set",
+            }
+            .to_string(),
+            temperature: inner.temperature,
+            target,
+            enabled,
+            icon: match enabled {
+                true => self.config.night.on,
+                false => self.config.night.off,
+            },
+        }
+    }
+
+    pub(crate) fn command(&self, command: commands::Night) -> NightState {
+        {
+            let mut inner = self.inner.lock().unwrap();
+            inner.mode = match command {
+                commands::Night::On => NightMode::On,
+                commands::Night::Off => NightMode::Off,
+                commands::Night::Auto => NightMode::Auto,
+                commands::Night::Set(kelvin) => NightMode::Set(kelvin),
+                commands::Night::Toggle => match self.target(inner.mode) < self.config.night.day {
+                    true => NightMode::Off,
+                    false => NightMode::On,
+                },
+                commands::Night::Get => inner.mode,
+            };
+        }
+        self.wake.notify_one();
+        let state = self.state();
+        update(state.clone());
+        state
+    }
+
+    /// Follows the target temperature, animating every change over the configured transition.
+    /// The gamma control is taken once night light is used and released by `off`, unless
+    /// the schedule is enabled. A new connection of `connections` takes it again.
+    pub(crate) async fn run(self, mut connections: watch::Receiver<Connection>) {
+        let frames = (self.config.night.transition.as_millis() / FRAME.as_millis()).max(1) as u32;
+        let mut gamma: Option<Gamma> = None;
+        let mut reconnected = false;
+        'schedule: loop {
+            let (from, to, mode) = {
+                let inner = self.inner.lock().unwrap();
+                (inner.temperature, self.target(inner.mode), inner.mode)
+            };
+            let used = self.config.night.enabled || mode != NightMode::Off;
+            reconnected |= connections.has_changed().unwrap_or(false);
+            if std::mem::take(&mut reconnected) && gamma.take().is_some() {
+                info!(target: "NightLight", "Reconnected, taking the gamma control again");
+            }
+            if used && gamma.is_none() {
+                let connection = connections.borrow_and_update().clone();
+                gamma = acquire(&connection).await;
+                if let Some(gamma) = &gamma {
+                    gamma.set(from);
+                }
+            }
+            if let Some(gamma) = gamma.as_ref().filter(|_| from != to) {
+                info!(target: "NightLight", "Changing temperature from {from}K to {to}K");
+                for frame in 1..=frames {
+                    let kelvin =
+                        from as f64 + (to as f64 - from as f64) * frame as f64 / frames as f64;
+                    let kelvin = kelvin.round() as u32;
+                    gamma.set(kelvin);
+                    self.inner.lock().unwrap().temperature = kelvin;
+                    tokio::select! {
+                        _ = tokio::time::sleep(FRAME) => {}
+                        _ = self.wake.notified() => continue 'schedule,
+                    }
+                }
+                update(self.state());
+            }
+            if !used {
+                if gamma.take().is_some() {
+                    info!(target: "NightLight", "Releasing the gamma control");
+                }
+                self.inner.lock().unwrap().temperature = to;
+            }
+            tokio::select! {
+                _ = tokio::time::sleep(Duration::from_secs(60)) => {}
+                _ = self.wake.notified() => {}
+                Ok(()) = connections.changed() => reconnected = true,
+            }
+        }
+    }
+}
+
+/// Takes the gamma control of all outputs, `None` if the compositor does not allow it
+async fn acquire(connection: &Connection) -> Option<Gamma> {
+    let connection = connection.clone();
+    match tokio::task::spawn_blocking(move || Gamma::new(&connection)).await {
+        Ok(Ok(gamma)) => {
+            info!(target: "NightLight", "Took the gamma control");
+            Some(gamma)
+        }
+        Ok(Err(err)) => {
+            error!(target: "NightLight", "Night light is unavailable: {err}");
+            None
+        }
+        Err(err) => {
+            error!(target: "NightLight", "Taking the gamma control failed: {err}");
+            None
+        }
+    }
+}
+
+fn update(state: NightState) {
+    if let Err(err) = eww_update(EwwVariable::Night(state)) {
+        error!("Unable to update EWW: {err:#?}");
+    }
+}
+
+pub fn client(command: commands::Night) -> Result<(), GlueError> {
+    let message =
+        daemon::client(commands::Command::Night(command)).map_err(GlueError::DaemonClient)?;
+    if !message.is_empty() {
+        println!("{}", String::from_utf8_lossy(&message));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sun_times_berlin() {
+        let date = NaiveDate::from_ymd_opt(2024, 6, 21).unwrap();
+        let (sunrise, sunset) = sun_times(date, 52.52, 13.405).unwrap();
+        // 04:43 and 21:33 CEST
+        assert!((sunrise - 163.0).abs() < 5.0, "sunrise {sunrise}");
+        assert!((sunset - 1173.0).abs() < 5.0, "sunset {sunset}");
+        assert!(sun_times(date, 80.0, 0.0).is_none());
+    }
+
+    #[test]
+    fn test_fade_around_night() {
+        let time = |h, m| NaiveTime::from_hms_opt(h, m, 0).unwrap();
+        let fade = Duration::from_secs(60 * 60);
+        let factor = |now| night_factor(now, time(20, 0), time(7, 0), fade);
+        assert_eq!(factor(time(12, 0)), 0.0);
+        assert_eq!(factor(time(20, 30)), 0.5);
+        assert_eq!(factor(time(23, 0)), 1.0);
+        assert_eq!(factor(time(3, 0)), 1.0);
+        assert_eq!(factor(time(7, 30)), 0.5);
+        assert_eq!(factor(time(8, 0)), 0.0);
+        assert_eq!(
+            night_factor(time(20, 30), time(20, 0), time(7, 0), Duration::ZERO),
+            1.0
+        );
+    }
+
+    #[test]
+    fn test_warm_whitepoint() {
+        assert_eq!(whitepoint(NEUTRAL), [1.0, 1.0, 1.0]);
+        let [red, green, blue] = whitepoint(3000);
+        assert_eq!(red, 1.0);
+        assert!(blue < green && green < red);
+        let table = ramp(4, NEUTRAL);
+        assert_eq!(table.len(), 4 * 3 * 2);
+        assert_eq!(table[6..8], u16::MAX.to_ne_bytes());
+    }
+}
@@ -0,0 +1,192 @@
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use hyprland::data::{Client, Clients, FullscreenMode};
+use hyprland::prelude::*;
+use log::{debug, error, info};
+use regex::Regex;
+use tokio::process::Command;
+use tokio::sync::Mutex;
+
+use crate::coffee::{set_reasons, CoffeeResponse};
+use crate::configuration::Configuration;
+use crate::error::GlueError;
+use crate::eww::{eww_update, EwwVariable};
+use crate::monitor::Monitor;
+use crate::{DaemonState, IdleState};
+
+/// Longest wait for a rule, a hung command must not stall the other monitors
+const RULE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Keeps the system awake while one of the `[coffee.rules]` matches
+pub(crate) struct CoffeeRules {
+    config: Arc<Configuration>,
+    state: Arc<Mutex<DaemonState>>,
+    apps: Vec<Regex>,
+    last: Option<Instant>,
+}
+
+impl CoffeeRules {
+    pub(crate) fn try_new(
+        config: Arc<Configuration>,
+        state: Arc<Mutex<DaemonState>>,
+    ) -> Option<Self> {
+        let rules = &config.coffee.rules;
+        // Rejected when the configuration is loaded
+        let apps = patterns(&rules.apps)
+            .inspect_err(|err| error!("Invalid coffee rule: {err}"))
+            .unwrap_or_default();
+        if !(rules.fullscreen || rules.media || rules.mic || !apps.is_empty()) {
+            return None;
+        }
+        Some(Self {
+            config,
+            state,
+            apps,
+            last: None,
+        })
+    }
+
+    async fn reasons(&self) -> Vec<String> {
+        let rules = &self.config.coffee.rules;
+        let mut reasons = Vec::new();
+        if rules.fullscreen && fullscreen().await {
+            reasons.push("fullscreen".to_string());
+        }
+        if rules.media && media_playing().await {
+            reasons.push("media".to_string());
+        }
+        if rules.mic && mic_in_use().await {
+            reasons.push("mic".to_string());
+        }
+        if !self.apps.is_empty() {
+            match limited(Clients::get_async()).await {
+                Some(Ok(clients)) => reasons.extend(
+                    clients
+                        .into_iter()
+                        .find(|client| matches(&self.apps, &client.class, &client.title))
+                        .map(|client| format!("app:{}", client.class)),
+                ),
+                Some(Err(err)) => debug!("Coffee rules: {err}"),
+                None => debug!("Coffee rules: Hyprland did not answer"),
+            }
+        }
+        reasons
+    }
+}
+
+#[async_trait]
+impl Monitor for CoffeeRules {
+    async fn update(&mut self) -> Result<(), GlueError> {
+        let interval = self.config.coffee.rules.interval;
+        if self.last.is_some_and(|last| last.elapsed() < interval) {
+            return Ok(());
+        }
+        self.last = Some(Instant::now());
+        let reasons = self.reasons().await;
+        let mut state = self.state.lock().await;
+        if set_reasons(&mut state, reasons)
+            .await
+            .map_err(GlueError::Coffee)?
+        {
+            info!("Automatic coffee: {:?}", state.auto_inhibit);
+            let response = CoffeeResponse::new(&self.config, &IdleState::from(&*state));
+            if let Err(err) = eww_update(EwwVariable::Coffee(response)) {
+                error!("Unable to update EWW: {err:#?}");
+            }
+        }
+        Ok(())
+    }
+
+    async fn event(&self) {}
+}
+
+/// Compiles the `coffee.rules.apps` patterns
+pub(crate) fn patterns(apps: &[String]) -> Result<Vec<Regex>, regex::Error> {
+    apps.iter().map(|pattern| Regex::new(pattern)).collect()
+}
+
+/// Result of `future`, `None` after `RULE_TIMEOUT`
+async fn limited<T>(future: impl Future<Output = T>) -> Option<T> {
+    tokio::time::timeout(RULE_TIMEOUT, future).await.ok()
+}
+
+fn matches(apps: &[Regex], class: &str, title: &str) -> bool {
+    apps.iter()
+        .any(|app| app.is_match(class) || app.is_match(title))
+}
+
+async fn fullscreen() -> bool {
+    match limited(Client::get_active_async()).await {
+        Some(Ok(Some(client))) => matches!(
+            client.fullscreen,
+            FullscreenMode::Fullscreen | FullscreenMode::MaximizedFullscreen
+        ),
+        Some(Ok(None)) => false,
+        Some(Err(err)) => {
+            debug!("Coffee rules: {err}");
+            false
+        }
+        None => {
+            debug!("Coffee rules: Hyprland did not answer");
+            false
+        }
+    }
+}
+
+async fn media_playing() -> bool {
+    output("playerctl", &["--all-players", "status"])
+        .await
+        .is_some_and(|status| status.lines().any(|line| line.trim() == "Playing"))
+}
+
+async fn mic_in_use() -> bool {
+    output("pactl", &["list", "source-outputs", "short"])
+        .await
+        .is_some_and(|outputs| !outputs.trim().is_empty())
+}
+
+/// Output of the command, which is killed after `RULE_TIMEOUT`
+async fn output(program: &str, args: &[&str]) -> Option<String> {
+    let command = Command::new(program).args(args).kill_on_drop(true).output();
+    match limited(command).await {
+        Some(Ok(output)) => Some(String::from_utf8_lossy(&output.stdout).to_string()),
+        Some(Err(err)) => {
+            debug!("Coffee rules: unable to run {program}: {err}");
+            None
+        }
+        None => {
+            error!("Coffee rules: {program} did not finish within {RULE_TIMEOUT:?}");
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_match_class_or_title() {
+        let apps = [
+            Regex::new("^(zoom|Slack)$").unwrap(),
+            Regex::new("Meet -").unwrap(),
+        ];
+        assert!(matches(&apps, "zoom", "Zoom Meeting"));
+        assert!(matches(&apps, "firefox", "Meet - abc-defg-hij"));
+        assert!(!matches(&apps, "firefox", "Mozilla Firefox"));
+    }
+
+    #[tokio::test]
+    async fn test_hung_command_times_out() {
+        let started = Instant::now();
+        assert_eq!(output("sleep", &["10"]).await, None);
+        assert!(started.elapsed() < RULE_TIMEOUT + Duration::from_secs(1));
+        assert_eq!(
+            output("echo", &["Playing"]).await.as_deref(),
+            Some("Playing\n")
+        );
+    }
+}
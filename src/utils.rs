@@ -1,10 +1,11 @@
 use std::{
+    future::Future,
+    path::PathBuf,
     sync::{Arc, Mutex},
     time::Duration,
 };
 
-use log::{error, info};
-use notify_rust::Notification;
+use log::info;
 use tokio::sync::Notify;
 
 #[derive(Clone, Debug)]
@@ -23,24 +24,24 @@ impl CancelableTimer {
         }
     }
 
-    pub fn start(&self) {
+    /// Runs `on_timeout` once the duration elapsed, unless the timer was canceled before
+    pub fn start<F, Fut>(&self, on_timeout: F)
+    where
+        F: FnOnce(Duration) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send,
+    {
         let notify = self.notify.clone();
         let is_canceled = self.is_canceled.clone();
         let duration = self.duration;
 
         tokio::spawn(async move {
             tokio::select! {
-                _ = tokio::time::sleep(duration) => {
+                _ = async {
+                    tokio::time::sleep(duration).await;
                     if !*is_canceled.lock().unwrap() {
-                        let result = Notification::new()
-                            .summary("Coffee still required?")
-                            .body(&format!("The System is coffeinated since {duration:#?}"))
-                            .show();
-                        if let Err(error) = result {
-                            error!("Unable to send notification: {error:#?}");
-                        }
+                        on_timeout(duration).await;
                     }
-                }
+                } => {}
                 _ = notify.notified() => {
                     info!("Timer was canceled!");
                 }
@@ -53,3 +54,62 @@ impl CancelableTimer {
         self.notify.notify_one();
     }
 }
+
+/// Directory for persistent daemon data: `$XDG_STATE_HOME/glue` (default `~/.local/state/glue`)
+pub fn state_dir() -> PathBuf {
+    let base = match std::env::var("XDG_STATE_HOME") {
+        Ok(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var("HOME").unwrap_or_default())
+            .join(".local")
+            .join("state"),
+    };
+    base.join("glue")
+}
+
+/// Matches `name` against a shell style pattern, supporting `*` and `?`
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let name = name.chars().collect::<Vec<_>>();
+    let (mut p, mut n) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, n));
+                p += 1;
+            }
+            Some(x) if *x == '?' || *x == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                Some((star, matched)) => {
+                    p = star + 1;
+                    n = matched + 1;
+                    backtrack = Some((star, matched + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|x| *x == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("intel_backlight", "intel_backlight"));
+        assert!(!glob_match("intel_backlight", "intel_backlight0"));
+        assert!(glob_match("ddcci*", "ddcci12"));
+        assert!(glob_match("*kbd_backlight*", "tpacpi::kbd_backlight"));
+        assert!(glob_match("acpi_video?", "acpi_video0"));
+        assert!(!glob_match("acpi_video?", "acpi_video"));
+        assert!(glob_match("*a*b", "xaaxb"));
+        assert!(!glob_match("*a*b", "xaaxbc"));
+        assert!(glob_match("*", ""));
+        assert!(!glob_match("", "x"));
+    }
+}
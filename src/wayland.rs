@@ -1,15 +1,23 @@
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
-use log::{debug, info, warn};
+use log::{debug, error, info, warn};
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tokio::sync::watch;
 use wayland_client::protocol::wl_display::WlDisplay;
 use wayland_client::protocol::wl_registry::{self, WlRegistry};
+use wayland_client::protocol::wl_seat::WlSeat;
 use wayland_client::protocol::{wl_compositor::WlCompositor, wl_surface::WlSurface};
 use wayland_client::{
     ConnectError as WLConnectionError, Dispatch, DispatchError, Proxy, QueueHandle,
 };
 use wayland_client::{Connection, EventQueue};
+use wayland_protocols::ext::idle_notify::v1::client::{
+    ext_idle_notification_v1::{self, ExtIdleNotificationV1},
+    ext_idle_notifier_v1::ExtIdleNotifierV1,
+};
 use wayland_protocols::wp::idle_inhibit::zv1::client::{
     zwp_idle_inhibit_manager_v1::ZwpIdleInhibitManagerV1, zwp_idle_inhibitor_v1::ZwpIdleInhibitorV1,
 };
@@ -20,6 +28,10 @@ pub enum WaylandClientError {
     ConnectionError(WLConnectionError),
     #[error("Roundtrip faild: {}", .0)]
     RoundTripError(DispatchError),
+    #[error("The compositor does not support {}", .0)]
+    Unsupported(&'static str),
+    #[error("The compositor removed {}", .0)]
+    Removed(&'static str),
 }
 
 #[derive(Default, Clone, Debug)]
@@ -111,12 +123,14 @@ impl Dispatch<WlRegistry, ()> for WaylandAppData {
                         warn!(target: "WaylandIdleInhibitor::GlobalRemove", "Compositor was removed!");
                         state.compositor = None;
                         state.surface = None;
+                        state.idle_inhibitor = None;
                     }
                 }
                 if let Some((_, idle_manager_name)) = &state.idle_manager {
                     if name == *idle_manager_name {
                         warn!(target: "WaylandIdleInhibitor::GlobalRemove", "IdleInhibitManager was removed!");
                         state.idle_manager = None;
+                        state.idle_inhibitor = None;
                     }
                 }
             }
@@ -125,9 +139,93 @@ impl Dispatch<WlRegistry, ()> for WaylandAppData {
     }
 }
 
+/// User activity reported by `ext_idle_notifier_v1`, holding the index of the timeout
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IdleEvent {
+    Idled(usize),
+    Resumed(usize),
+}
+
+#[derive(Default)]
+struct WaylandIdleNotifyData {
+    seat: Option<WlSeat>,
+    notifier: Option<ExtIdleNotifierV1>,
+    sender: Option<UnboundedSender<IdleEvent>>,
+}
+
+impl Dispatch<WlRegistry, ()> for WaylandIdleNotifyData {
+    fn event(
+        state: &mut Self,
+        proxy: &WlRegistry,
+        event: <WlRegistry as wayland_client::Proxy>::Event,
+        _data: &(),
+        _conn: &Connection,
+        qhandle: &QueueHandle<Self>,
+    ) {
+        if let wl_registry::Event::Global {
+            name,
+            interface,
+            version,
+        } = event
+        {
+            if interface == WlSeat::interface().name && state.seat.is_none() {
+                debug!(target: "WaylandIdleNotifier::WlRegister::Event::Global", "Adding Seat");
+                state.seat = Some(proxy.bind(name, version.min(1), qhandle, ()));
+            } else if interface == ExtIdleNotifierV1::interface().name && state.notifier.is_none() {
+                debug!(target: "WaylandIdleNotifier::WlRegister::Event::Global", "Adding IdleNotifier");
+                state.notifier = Some(proxy.bind(name, version.min(1), qhandle, ()));
+            }
+        }
+    }
+}
+
+impl Dispatch<WlSeat, ()> for WaylandIdleNotifyData {
+    fn event(
+        _state: &mut Self,
+        _proxy: &WlSeat,
+        _event: <WlSeat as Proxy>::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qhandle: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<ExtIdleNotifierV1, ()> for WaylandIdleNotifyData {
+    fn event(
+        _state: &mut Self,
+        _proxy: &ExtIdleNotifierV1,
+        _event: <ExtIdleNotifierV1 as Proxy>::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qhandle: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<ExtIdleNotificationV1, usize> for WaylandIdleNotifyData {
+    fn event(
+        state: &mut Self,
+        _proxy: &ExtIdleNotificationV1,
+        event: <ExtIdleNotificationV1 as Proxy>::Event,
+        index: &usize,
+        _conn: &Connection,
+        _qhandle: &QueueHandle<Self>,
+    ) {
+        let event = match event {
+            ext_idle_notification_v1::Event::Idled => IdleEvent::Idled(*index),
+            ext_idle_notification_v1::Event::Resumed => IdleEvent::Resumed(*index),
+            _ => return,
+        };
+        if let Some(sender) = &state.sender {
+            let _ = sender.send(event);
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct WaylandClient {
-    _connection: Connection,
+    connection: Connection,
     event_queue: Arc<Mutex<EventQueue<WaylandAppData>>>,
     qhandle: QueueHandle<WaylandAppData>,
     app_data: WaylandAppData,
@@ -152,25 +250,28 @@ impl WaylandClient {
         Ok(WaylandClient {
             app_data,
             qhandle,
-            _connection: connection,
+            connection,
             _registry: registry,
             _display: display,
             event_queue,
         })
     }
 
+    pub fn connection(&self) -> &Connection {
+        &self.connection
+    }
+
     pub fn inhibit(&mut self) -> Result<(), WaylandClientError> {
-        // let Some(surface) = self.client.app_data.surface;
         let data = self.app_data.clone();
         let Some((idle_manager, _)) = &data.idle_manager else {
             warn!(target: "WaylandIdleInhibitor::set_inhibit_idle", "Tried to change idle inhibitor status without loaded idle inhibitor manager!");
-            return Ok(());
+            return Err(WaylandClientError::Removed("zwp_idle_inhibit_manager_v1"));
         };
 
         if data.idle_inhibitor.is_none() {
             let Some(surface) = &data.surface else {
                 warn!(target: "WaylandIdleInhibitor::set_inhibit_idle", "Tried to change idle inhibitor status without loaded WlSurface!");
-                return Ok(());
+                return Err(WaylandClientError::Removed("wl_compositor"));
             };
             self.app_data.idle_inhibitor =
                 Some(idle_manager.create_inhibitor(surface, &self.qhandle, ()));
@@ -184,9 +285,38 @@ impl WaylandClient {
         Ok(())
     }
 
-    pub fn get(&mut self) -> Result<WaylandIdle, WaylandClientError> {
+    pub fn get(&self) -> WaylandIdle {
         let inhibited = self.app_data.idle_inhibitor.is_some();
-        Ok(WaylandIdle { inhibited })
+        WaylandIdle { inhibited }
+    }
+
+    /// Processes pending events, fails if the connection was lost or, with `inhibitor`,
+    /// the compositor removed the globals required for the idle inhibitor
+    pub fn check(&mut self, inhibitor: bool) -> Result<(), WaylandClientError> {
+        self.event_queue
+            .lock()
+            .unwrap()
+            .roundtrip(&mut self.app_data)
+            .map_err(WaylandClientError::RoundTripError)?;
+        if !inhibitor {
+            return Ok(());
+        }
+        if self.app_data.surface.is_none() {
+            return Err(WaylandClientError::Removed("wl_compositor"));
+        }
+        if self.app_data.idle_manager.is_none() {
+            return Err(WaylandClientError::Removed("zwp_idle_inhibit_manager_v1"));
+        }
+        Ok(())
+    }
+
+    /// Replaces the connection and binds the globals again, the idle inhibitor is not restored
+    pub fn reconnect(&mut self, inhibitor: bool) -> Result<(), WaylandClientError> {
+        let mut client = Self::new()?;
+        client.check(inhibitor)?;
+        *self = client;
+        info!(target: "WaylandIdleInhibitor::reconnect", "Reconnected to the compositor");
+        Ok(())
     }
 
     pub fn release(&mut self) -> Result<(), WaylandClientError> {
@@ -204,6 +334,94 @@ impl WaylandClient {
     }
 }
 
+/// Reports when the user was inactive for any of the `timeouts` and when the activity resumed,
+/// subscribing again on every connection of `connections`, e.g. after a compositor restart.
+/// The compositor does not report idling while an idle inhibitor (coffee) is active.
+pub fn idle_notifications(
+    mut connections: watch::Receiver<Connection>,
+    timeouts: Vec<Duration>,
+    what: &'static str,
+) -> UnboundedReceiver<IdleEvent> {
+    let (sender, receiver) = unbounded_channel();
+    tokio::spawn(async move {
+        loop {
+            let connection = connections.borrow_and_update().clone();
+            let timeouts = timeouts.clone();
+            let subscribed =
+                tokio::task::spawn_blocking(move || subscribe(&connection, &timeouts)).await;
+            match subscribed {
+                Ok(Ok(mut events)) => loop {
+                    // A closed `events` waits for the next connection
+                    tokio::select! {
+                        Some(event) = events.recv() => {
+                            if sender.send(event).is_err() {
+                                return;
+                            }
+                        }
+                        changed = connections.changed() => match changed {
+                            Ok(()) => break,
+                            Err(_) => return,
+                        },
+                    }
+                },
+                Ok(Err(err)) => {
+                    error!(target: "WaylandIdleNotifier", "{what} unavailable until the next connection: {err}");
+                    if connections.changed().await.is_err() {
+                        return;
+                    }
+                }
+                Err(err) => {
+                    error!(target: "WaylandIdleNotifier", "Subscribing {what} failed: {err}");
+                    return;
+                }
+            }
+        }
+    });
+    receiver
+}
+
+/// Dispatches the idle notifications of `connection` on a dedicated thread, until the
+/// connection is lost or the receiver is dropped
+fn subscribe(
+    connection: &Connection,
+    timeouts: &[Duration],
+) -> Result<UnboundedReceiver<IdleEvent>, WaylandClientError> {
+    let (sender, receiver) = unbounded_channel();
+    let mut event_queue = connection.new_event_queue();
+    let qhandle = event_queue.handle();
+    let mut data = WaylandIdleNotifyData {
+        sender: Some(sender),
+        ..Default::default()
+    };
+    let _registry = connection.display().get_registry(&qhandle, ());
+    event_queue
+        .roundtrip(&mut data)
+        .map_err(WaylandClientError::RoundTripError)?;
+    let (Some(notifier), Some(seat)) = (&data.notifier, &data.seat) else {
+        return Err(WaylandClientError::Unsupported("ext_idle_notifier_v1"));
+    };
+    let notifications = timeouts
+        .iter()
+        .enumerate()
+        .map(|(index, timeout)| {
+            notifier.get_idle_notification(timeout.as_millis() as u32, seat, &qhandle, index)
+        })
+        .collect::<Vec<_>>();
+    std::thread::spawn(move || {
+        let _notifications = notifications;
+        loop {
+            if let Err(err) = event_queue.blocking_dispatch(&mut data) {
+                error!(target: "WaylandIdleNotifier", "Dispatching idle notifications failed: {err}");
+                return;
+            }
+            if data.sender.as_ref().is_none_or(UnboundedSender::is_closed) {
+                return;
+            }
+        }
+    });
+    Ok(receiver)
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct WaylandIdle {
     pub inhibited: bool,
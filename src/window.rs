@@ -0,0 +1,126 @@
+use hyprland::data::{Client, FullscreenMode};
+use hyprland::prelude::*;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::configuration::Window;
+use crate::error::WindowError;
+use crate::eww::{eww_update, EwwVariable};
+
+/// Focused window, empty if no window is focused
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct ActiveWindow {
+    class: String,
+    /// Title after the `[[window.rewrite]]` rules and `window.max_length`
+    title: String,
+    fullscreen: bool,
+    floating: bool,
+    pid: i32,
+}
+
+impl ActiveWindow {
+    fn new(titles: &Titles, client: Option<Client>) -> Self {
+        let Some(client) = client else {
+            return Self::default();
+        };
+        Self {
+            title: titles.title(&client.title),
+            class: client.class,
+            fullscreen: client.fullscreen != FullscreenMode::None,
+            floating: client.floating,
+            pid: client.pid,
+        }
+    }
+
+    pub fn get(titles: &Titles) -> Result<Self, WindowError> {
+        let client = Client::get_active().map_err(WindowError::Hyprland)?;
+        Ok(Self::new(titles, client))
+    }
+}
+
+/// `[window]` with the rewrite patterns compiled once
+#[derive(Debug)]
+pub struct Titles {
+    rewrite: Vec<(Regex, String)>,
+    max_length: Option<usize>,
+}
+
+impl Titles {
+    pub fn new(config: &Window) -> Result<Self, regex::Error> {
+        let rewrite = config
+            .rewrite
+            .iter()
+            .map(|rule| Ok((Regex::new(&rule.pattern)?, rule.replace.clone())))
+            .collect::<Result<_, regex::Error>>()?;
+        Ok(Self {
+            rewrite,
+            max_length: config.max_length,
+        })
+    }
+
+    /// Applies the first matching rewrite rule and shortens the title to `max_length` characters
+    fn title(&self, title: &str) -> String {
+        let title = self
+            .rewrite
+            .iter()
+            .find_map(|(pattern, replace)| {
+                pattern
+                    .is_match(title)
+                    .then(|| pattern.replace(title, replace.as_str()).to_string())
+            })
+            .unwrap_or_else(|| title.to_string());
+        shorten(title, self.max_length)
+    }
+}
+
+fn shorten(title: String, max_length: Option<usize>) -> String {
+    match max_length {
+        Some(max_length) if title.chars().count() > max_length => {
+            let mut short = title
+                .chars()
+                .take(max_length.saturating_sub(1))
+                .collect::<String>();
+            short.push('…');
+            short
+        }
+        _ => title,
+    }
+}
+
+pub fn client(config: &Window) -> Result<(), WindowError> {
+    let titles = Titles::new(config).map_err(|err| WindowError::Rewrite(err.to_string()))?;
+    let window = ActiveWindow::get(&titles)?;
+    println!("{}", serde_json::to_string(&window).unwrap());
+    Ok(())
+}
+
+pub fn eww_window_update(titles: &Titles) -> Result<(), WindowError> {
+    eww_update(EwwVariable::ActiveWindow(ActiveWindow::get(titles)?)).map_err(WindowError::Command)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::configuration::TitleRewrite;
+
+    #[test]
+    fn test_rewrite_and_shorten_title() {
+        let config = Window {
+            max_length: Some(12),
+            rewrite: vec![
+                TitleRewrite {
+                    pattern: "(.*) — Mozilla Firefox$".to_string(),
+                    replace: "web: $1".to_string(),
+                },
+                TitleRewrite {
+                    pattern: "^nvim (.*)".to_string(),
+                    replace: "vim: $1".to_string(),
+                },
+            ],
+        };
+        let titles = Titles::new(&config).unwrap();
+        assert_eq!(titles.title("GitHub — Mozilla Firefox"), "web: GitHub");
+        assert_eq!(titles.title("nvim src/window.rs"), "vim: src/wi…");
+        assert_eq!(titles.title("kitty"), "kitty");
+    }
+}
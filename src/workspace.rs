@@ -1,116 +1,529 @@
-use std::fmt::Display;
+use std::collections::{HashMap, HashSet};
 
-use hyprland::data::{Monitor, Workspace, Workspaces};
+use hyprland::data::{Clients, Monitor, Monitors, Workspace, Workspaces};
 use hyprland::prelude::*;
+use hyprland::shared::Address;
+use log::debug;
+use serde::Serialize;
 
+use crate::commands::{self, Command};
+use crate::configuration::{Hyprland, WorkspaceFormat};
+use crate::daemon;
+use crate::desktop;
 use crate::error::WorkspaceError;
 use crate::eww::{eww_update, EwwVariable};
 
-pub fn eww_workspaces(default_spaces: usize) -> Result<String, WorkspaceError> {
-    let workspaces = Workspaces::get().map_err(WorkspaceError::Hyprland)?;
-    Ok(EwwWorkspaces::new(workspaces, default_spaces).to_string())
+/// Workspaces and their windows
+struct Layout {
+    workspaces: Vec<Workspace>,
+    clients: HashMap<i32, Vec<EwwClient>>,
 }
 
-pub fn eww_workspace_update(default_spaces: usize) -> Result<(), WorkspaceError> {
-    eww_update(EwwVariable::Workspace(eww_workspaces(default_spaces)?))
-        .map_err(WorkspaceError::Command)
+impl Layout {
+    /// `urgent` are the windows which demand attention, only known to the daemon
+    fn get(config: &Hyprland, urgent: &HashSet<Address>) -> Result<Self, WorkspaceError> {
+        let workspaces = Workspaces::get()
+            .map_err(WorkspaceError::Hyprland)?
+            .to_vec();
+        let mut clients: HashMap<i32, Vec<EwwClient>> = HashMap::new();
+        for client in Clients::get().map_err(WorkspaceError::Hyprland)? {
+            if client.mapped {
+                clients
+                    .entry(client.workspace.id)
+                    .or_default()
+                    .push(EwwClient::new(
+                        config,
+                        client.class,
+                        client.title,
+                        urgent.contains(&client.address),
+                    ));
+            }
+        }
+        Ok(Self {
+            workspaces,
+            clients,
+        })
+    }
+}
+
+/// Urgent windows of the daemon, which is the only one to see the Hyprland events,
+/// none if the daemon is not running
+pub fn urgent() -> HashSet<Address> {
+    daemon::client(Command::Workspace(commands::Workspace::Urgent))
+        .map_err(|err| err.to_string())
+        .and_then(|message| serde_json::from_slice(&message).map_err(|err| err.to_string()))
+        .unwrap_or_else(|err| {
+            debug!("Unable to get the urgent windows: {err}");
+            HashSet::new()
+        })
+}
+
+/// Workspaces of all monitors or only of `monitor`, which shows its own active workspace
+pub fn eww_workspaces(
+    config: &Hyprland,
+    default_spaces: usize,
+    format: WorkspaceFormat,
+    monitor: Option<&str>,
+    urgent: &HashSet<Address>,
+) -> Result<String, WorkspaceError> {
+    let layout = Layout::get(config, urgent)?;
+    let active = match monitor {
+        None => Monitor::get_active().map_err(WorkspaceError::Hyprland)?,
+        Some(name) => Monitors::get()
+            .map_err(WorkspaceError::Hyprland)?
+            .into_iter()
+            .find(|x| x.name == name)
+            .ok_or_else(|| WorkspaceError::UnknownMonitor(name.to_string()))?,
+    };
+    let workspaces = EwwWorkspaces::new(
+        config,
+        &layout,
+        default_spaces,
+        active.active_workspace.id,
+        monitor,
+    );
+    Ok(workspaces.format(config, format))
+}
+
+/// Updates the `workspace` eww variable and `workspace_<monitor>` of every monitor
+pub fn eww_workspace_update(
+    config: &Hyprland,
+    default_spaces: usize,
+    format: WorkspaceFormat,
+    urgent: &HashSet<Address>,
+) -> Result<(), WorkspaceError> {
+    let layout = Layout::get(config, urgent)?;
+    let monitors = Monitors::get().map_err(WorkspaceError::Hyprland)?.to_vec();
+    let focused = monitors
+        .iter()
+        .find(|x| x.focused)
+        .map_or(0, |x| x.active_workspace.id);
+    let all = EwwWorkspaces::new(config, &layout, default_spaces, focused, None);
+    eww_update(EwwVariable::Workspace(all.format(config, format)))
+        .map_err(WorkspaceError::Command)?;
+    for monitor in monitors {
+        let own = EwwWorkspaces::new(
+            config,
+            &layout,
+            default_spaces,
+            monitor.active_workspace.id,
+            Some(&monitor.name),
+        );
+        eww_update(EwwVariable::MonitorWorkspace(
+            monitor.name,
+            own.format(config, format),
+        ))
+        .map_err(WorkspaceError::Command)?;
+    }
+    Ok(())
 }
 
 struct EwwWorkspaces(Vec<EwwWorkspaceButton>);
 
 impl EwwWorkspaces {
-    fn new(workspaces: Workspaces, default_spaces: usize) -> Self {
-        let mut buttons = workspaces
+    /// Default workspaces, which do not exist yet, are shown on every monitor. Occupied
+    /// workspaces beyond the default range, named and special workspaces follow them.
+    fn new(
+        config: &Hyprland,
+        layout: &Layout,
+        default_spaces: usize,
+        active: i32,
+        monitor: Option<&str>,
+    ) -> Self {
+        let mut buttons = layout
+            .workspaces
             .iter()
-            .map(|x| x.into())
+            .filter(|x| monitor.is_none_or(|monitor| x.monitor == monitor))
+            .map(|x| {
+                let clients = layout.clients.get(&x.id).cloned().unwrap_or_default();
+                EwwWorkspaceButton::new(config, x, active, clients)
+            })
             .collect::<Vec<EwwWorkspaceButton>>();
-        buttons.sort_by(|a, b| a.id.cmp(&b.id));
+        buttons.sort_by_key(|a| a.id);
+        let exists = |id: i32| layout.workspaces.iter().any(|x| x.id == id);
         let mut results = Vec::new();
 
-        for i in 0..(default_spaces) {
-            if let Ok(i) = buttons.binary_search_by_key(&((i + 1) as i32), |x| x.id) {
-                results.push(buttons[i].clone());
-            } else {
-                results.push(EwwWorkspaceButton::empty(i as i32 + 1))
+        for i in 1..=(default_spaces as i32) {
+            match buttons.iter().find(|x| x.id == i) {
+                Some(button) => results.push(button.clone()),
+                None if !exists(i) => results.push(EwwWorkspaceButton::empty(config, i)),
+                None => {}
             }
         }
+        let extra = |x: &&EwwWorkspaceButton| {
+            (x.id < 1 || x.id > default_spaces as i32)
+                && (x.windows > 0 || x.state == EwwWorkspaceButtonState::Active)
+        };
+        let (special, regular): (Vec<_>, Vec<_>) = buttons
+            .iter()
+            .filter(extra)
+            .partition(|x| x.state == EwwWorkspaceButtonState::Special);
+        // Numbered before named workspaces, which have negative ids
+        let (numbered, named): (Vec<_>, Vec<_>) = regular.into_iter().partition(|x| x.id > 0);
+        results.extend(numbered.into_iter().cloned());
+        results.extend(named.into_iter().cloned());
+        results.extend(special.into_iter().cloned());
         Self(results)
     }
+
+    fn format(&self, config: &Hyprland, format: WorkspaceFormat) -> String {
+        match format {
+            WorkspaceFormat::Yuck => self.yuck(config),
+            WorkspaceFormat::Json => serde_json::to_string(&self.0).unwrap_or_default(),
+        }
+    }
+
+    fn yuck(&self, config: &Hyprland) -> String {
+        format!(
+            "(box :class \"workspaces\" :orientation \"h\" :space-evenly \"false\" {} )",
+            self.0
+                .iter()
+                .map(|x| x.yuck(config))
+                .collect::<Vec<String>>()
+                .join(" ")
+        )
+    }
 }
 
-#[derive(Clone)]
+#[derive(Serialize, Clone, Debug, PartialEq)]
 struct EwwWorkspaceButton {
     id: i32,
+    /// Name without the `special:` prefix
+    name: String,
     state: EwwWorkspaceButtonState,
+    /// `hyprland.workspace_icons` of the workspace or the icon of the state
+    icon: String,
+    windows: u16,
+    clients: Vec<EwwClient>,
+    monitor: String,
+    urgent: bool,
+}
+
+/// Window of a workspace
+#[derive(Serialize, Clone, Debug, PartialEq)]
+struct EwwClient {
+    class: String,
+    title: String,
+    /// `hyprland.window_icons` of the class or the icon name of its desktop entry
+    icon: String,
+    urgent: bool,
 }
 
-#[derive(Clone)]
+impl EwwClient {
+    fn new(config: &Hyprland, class: String, title: String, urgent: bool) -> Self {
+        let icon = match config.window_icons.get(&class) {
+            Some(icon) => icon.clone(),
+            None => desktop::icon(&class),
+        };
+        Self {
+            class,
+            title,
+            icon,
+            urgent,
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
 enum EwwWorkspaceButtonState {
-    Emtpy,
+    Empty,
     Active,
     Contains,
+    /// Scratchpad workspace
+    Special,
+    /// Holds a window, which demands attention
+    Urgent,
 }
 
-impl EwwWorkspaceButton {
-    fn empty(id: i32) -> Self {
-        Self {
-            id,
-            state: EwwWorkspaceButtonState::Emtpy,
+impl EwwWorkspaceButtonState {
+    fn class(&self) -> &'static str {
+        match self {
+            EwwWorkspaceButtonState::Empty => "empty",
+            EwwWorkspaceButtonState::Active => "active",
+            EwwWorkspaceButtonState::Contains => "contains",
+            EwwWorkspaceButtonState::Special => "special",
+            EwwWorkspaceButtonState::Urgent => "urgent",
         }
     }
-}
 
-fn focus_workspace(workspace_id: i32) -> String {
-    format!("hyprctl dispatch workspace {workspace_id}")
+    fn icon(&self, config: &Hyprland) -> char {
+        match self {
+            EwwWorkspaceButtonState::Empty => config.state_icons.empty,
+            EwwWorkspaceButtonState::Active => config.state_icons.active,
+            EwwWorkspaceButtonState::Contains => config.state_icons.contains,
+            EwwWorkspaceButtonState::Special => config.state_icons.special,
+            EwwWorkspaceButtonState::Urgent => config.state_icons.urgent,
+        }
+    }
 }
 
-impl From<&Workspace> for EwwWorkspaceButton {
-    fn from(value: &Workspace) -> Self {
-        let mut state = if value.windows == 0 {
-            EwwWorkspaceButtonState::Emtpy
+const SPECIAL: &str = "special:";
+
+impl EwwWorkspaceButton {
+    fn new(config: &Hyprland, value: &Workspace, active: i32, clients: Vec<EwwClient>) -> Self {
+        let special = value.name.strip_prefix(SPECIAL);
+        let urgent = clients.iter().any(|x| x.urgent);
+        let state = if special.is_some() || value.name == "special" {
+            EwwWorkspaceButtonState::Special
+        } else if value.id == active {
+            EwwWorkspaceButtonState::Active
+        } else if urgent {
+            EwwWorkspaceButtonState::Urgent
+        } else if value.windows == 0 {
+            EwwWorkspaceButtonState::Empty
         } else {
             EwwWorkspaceButtonState::Contains
         };
-        if Monitor::get_active().unwrap().active_workspace.id == value.id {
-            state = EwwWorkspaceButtonState::Active;
-        };
+        let name = special.unwrap_or(&value.name).to_string();
         EwwWorkspaceButton {
             id: value.id,
+            icon: icon(config, value.id, &name, state),
+            name,
             state,
+            windows: value.windows,
+            clients,
+            monitor: value.monitor.clone(),
+            urgent,
         }
     }
-}
 
-impl Display for EwwWorkspaceButton {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "(button :onclick \"{}\" :class \"workspace\"  (box :class \"workspace-{}\" :space-evenly \"false\" \"{}\"))",
-            focus_workspace(self.id),
-            match self.state {
-                EwwWorkspaceButtonState::Emtpy => "empty",
-                EwwWorkspaceButtonState::Active => "active",
-                EwwWorkspaceButtonState::Contains => "contains",
-            },
-            match self.state {
-                EwwWorkspaceButtonState::Emtpy => '',
-                EwwWorkspaceButtonState::Active => '',
-                EwwWorkspaceButtonState::Contains => '',
-            }
-        )
+    fn empty(config: &Hyprland, id: i32) -> Self {
+        let state = EwwWorkspaceButtonState::Empty;
+        Self {
+            id,
+            name: id.to_string(),
+            state,
+            icon: icon(config, id, &id.to_string(), state),
+            windows: 0,
+            clients: Vec::new(),
+            monitor: String::new(),
+            urgent: false,
+        }
+    }
+
+    /// Arguments of `hyprctl dispatch`, which focus the workspace
+    fn dispatch(&self) -> String {
+        match self.state {
+            EwwWorkspaceButtonState::Special => format!("togglespecialworkspace {}", self.name),
+            // Negative ids are relative to the current workspace
+            _ if self.id < 1 => format!("workspace name:{}", self.name),
+            _ => format!("workspace {}", self.id),
+        }
+    }
+
+    /// Fills the placeholders of `hyprland.template`, the names from Hyprland are escaped
+    /// for yuck strings
+    fn yuck(&self, config: &Hyprland) -> String {
+        config
+            .template
+            .replace("{id}", &self.id.to_string())
+            .replace("{name}", &escape(&self.name))
+            .replace("{state}", self.state.class())
+            .replace("{icon}", &self.icon)
+            .replace("{dispatch}", &escape(&self.dispatch()))
+            .replace("{windows}", &self.windows.to_string())
+            .replace("{monitor}", &escape(&self.monitor))
+            .replace("{urgent}", &self.urgent.to_string())
     }
 }
 
-impl Display for EwwWorkspaces {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "(box :class \"workspaces\" :orientation \"h\" :space-evenly \"false\" {} )",
-            self.0
-                .iter()
-                .map(|x| x.to_string())
-                .collect::<Vec<String>>()
-                .join(" ")
-        )
+/// Escapes `\` and `"` to keep a value within a yuck string
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Icon of the workspace name or id, falling back to the icon of the state
+fn icon(config: &Hyprland, id: i32, name: &str, state: EwwWorkspaceButtonState) -> String {
+    config
+        .workspace_icons
+        .get(name)
+        .or_else(|| config.workspace_icons.get(&id.to_string()))
+        .cloned()
+        .unwrap_or_else(|| state.icon(config).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyprland::shared::Address;
+
+    fn workspace(id: i32, name: &str, monitor: &str, windows: u16) -> Workspace {
+        Workspace {
+            id,
+            name: name.to_string(),
+            monitor: monitor.to_string(),
+            monitor_id: None,
+            windows,
+            fullscreen: false,
+            last_window: Address::new("0"),
+            last_window_title: String::new(),
+        }
+    }
+
+    fn layout(workspaces: &[Workspace]) -> Layout {
+        Layout {
+            workspaces: workspaces.to_vec(),
+            clients: HashMap::new(),
+        }
+    }
+
+    fn ids(workspaces: EwwWorkspaces) -> Vec<(i32, EwwWorkspaceButtonState)> {
+        workspaces.0.iter().map(|x| (x.id, x.state)).collect()
+    }
+
+    #[test]
+    fn test_workspaces_of_monitor() {
+        let config = Hyprland::default();
+        let workspaces = [workspace(1, "1", "eDP-1", 2), workspace(2, "2", "DP-1", 1)];
+        assert_eq!(
+            ids(EwwWorkspaces::new(
+                &config,
+                &layout(&workspaces),
+                3,
+                2,
+                Some("DP-1")
+            )),
+            [
+                (2, EwwWorkspaceButtonState::Active),
+                (3, EwwWorkspaceButtonState::Empty)
+            ]
+        );
+        assert_eq!(
+            ids(EwwWorkspaces::new(
+                &config,
+                &layout(&workspaces),
+                3,
+                1,
+                Some("eDP-1")
+            )),
+            [
+                (1, EwwWorkspaceButtonState::Active),
+                (3, EwwWorkspaceButtonState::Empty)
+            ]
+        );
+        assert_eq!(
+            ids(EwwWorkspaces::new(
+                &config,
+                &layout(&workspaces),
+                3,
+                1,
+                None
+            ))
+            .len(),
+            3
+        );
+    }
+
+    #[test]
+    fn test_named_special_and_extra_workspaces() {
+        let mut config = Hyprland::default();
+        config
+            .workspace_icons
+            .insert("web".to_string(), "W".to_string());
+        config
+            .workspace_icons
+            .insert("7".to_string(), "7".to_string());
+        let workspaces = [
+            workspace(-98, "special:scratch", "eDP-1", 1),
+            workspace(-1337, "web", "eDP-1", 3),
+            workspace(7, "7", "eDP-1", 1),
+            workspace(9, "9", "eDP-1", 0),
+            workspace(1, "1", "eDP-1", 1),
+        ];
+        let result = EwwWorkspaces::new(&config, &layout(&workspaces), 2, 1, None);
+        assert_eq!(
+            ids(result),
+            [
+                (1, EwwWorkspaceButtonState::Active),
+                (2, EwwWorkspaceButtonState::Empty),
+                (7, EwwWorkspaceButtonState::Contains),
+                (-1337, EwwWorkspaceButtonState::Contains),
+                (-98, EwwWorkspaceButtonState::Special),
+            ]
+        );
+        let result = EwwWorkspaces::new(&config, &layout(&workspaces), 2, 1, None);
+        let dispatch = result.0.iter().map(|x| x.dispatch()).collect::<Vec<_>>();
+        assert_eq!(dispatch[3], "workspace name:web");
+        assert_eq!(dispatch[4], "togglespecialworkspace scratch");
+        let icons = result.0.iter().map(|x| x.icon.as_str()).collect::<Vec<_>>();
+        assert_eq!(icons[2..4], ["7", "W"]);
+        assert_eq!(icons[4], config.state_icons.special.to_string());
+    }
+
+    #[test]
+    fn test_render_template() {
+        let config = Hyprland::default();
+        let button = EwwWorkspaceButton::new(&config, &workspace(3, "3", "DP-1", 0), 3, Vec::new());
+        assert_eq!(
+            button.yuck(&config),
+            format!(
+                "(button :onclick \"hyprctl dispatch workspace 3\" :class \"workspace\"  (box :class \"workspace-active\" :space-evenly \"false\" \"{}\"))",
+                config.state_icons.active
+            )
+        );
+        let config = Hyprland {
+            template: "{name}:{state}:{windows}".to_string(),
+            ..Hyprland::default()
+        };
+        assert_eq!(
+            EwwWorkspaceButton::empty(&config, 2).yuck(&config),
+            "2:empty:0"
+        );
+        assert_eq!(
+            serde_json::to_string(&button).unwrap(),
+            format!(
+                r#"{{"id":3,"name":"3","state":"active","icon":"{}","windows":0,"clients":[],"monitor":"DP-1","urgent":false}}"#,
+                config.state_icons.active
+            )
+        );
+    }
+
+    #[test]
+    fn test_render_template_escapes_names() {
+        let config = Hyprland {
+            template: "(button :onclick \"hyprctl dispatch {dispatch}\" \"{name}\")".to_string(),
+            ..Hyprland::default()
+        };
+        let workspace = workspace(-1337, r#"my "web" \ ws"#, "DP-1", 1);
+        let button = EwwWorkspaceButton::new(&config, &workspace, 3, Vec::new());
+        assert_eq!(
+            button.yuck(&config),
+            r#"(button :onclick "hyprctl dispatch workspace name:my \"web\" \\ ws" "my \"web\" \\ ws")"#
+        );
+    }
+
+    #[test]
+    fn test_clients_of_workspace() {
+        let mut config = Hyprland::default();
+        config
+            .window_icons
+            .insert("kitty".to_string(), "terminal".to_string());
+        let mut layout = layout(&[workspace(1, "1", "eDP-1", 1)]);
+        let client = EwwClient::new(&config, "kitty".to_string(), "~".to_string(), false);
+        layout.clients.insert(1, vec![client.clone()]);
+        let result = EwwWorkspaces::new(&config, &layout, 2, 1, None);
+        assert_eq!(result.0[0].clients, [client]);
+        assert_eq!(result.0[0].clients[0].icon, "terminal");
+        assert!(result.0[1].clients.is_empty());
+    }
+
+    #[test]
+    fn test_urgent_workspace() {
+        let config = Hyprland::default();
+        let mut layout = layout(&[workspace(1, "1", "eDP-1", 1), workspace(2, "2", "eDP-1", 1)]);
+        for id in [1, 2] {
+            let client = EwwClient::new(&config, "kitty".to_string(), "~".to_string(), true);
+            layout.clients.insert(id, vec![client]);
+        }
+        let result = EwwWorkspaces::new(&config, &layout, 2, 1, None);
+        // The focused workspace is active, even with an urgent window
+        assert_eq!(
+            ids(result),
+            [
+                (1, EwwWorkspaceButtonState::Active),
+                (2, EwwWorkspaceButtonState::Urgent)
+            ]
+        );
     }
 }